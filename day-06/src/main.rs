@@ -1,3 +1,6 @@
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
 use bitflags::bitflags;
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -17,39 +20,83 @@ fn main() {
 }
 
 fn distinct_guard_positions(s: &str) -> usize {
-    use Direction::*;
+    let [path] = &guard_paths(s, Turn::Right)[..] else {
+        panic!("Expected exactly one guard");
+    };
+    assert_eq!(Outcome::Exited, path.outcome);
 
-    let (grid, max, mut guard) = parse(s);
+    let visited = path.steps.iter().map(|&(coord, _)| coord);
+    visited.collect::<BTreeSet<_>>().len()
+}
 
-    let mut direction = U;
-    let mut visited = BTreeSet::new();
+/// Every guard walks independently; other guards do not act as
+/// obstacles.
+fn guard_paths(s: &str, turn: Turn) -> Vec<GuardPath> {
+    let (grid, max, guards) = parse_guards(s);
 
-    loop {
-        visited.insert(guard);
+    guards
+        .into_iter()
+        .map(|guard| walk(&grid, max, guard, turn))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GuardPath {
+    /// Every position the guard occupies, in order. Turning in place
+    /// produces another entry for the same square.
+    pub steps: Vec<Guard>,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Outcome {
+    Exited,
+    Looped,
+}
+
+fn walk(grid: &Grid, max: Max, (mut guard, mut direction): Guard, turn: Turn) -> GuardPath {
+    let mut visited = BTreeMap::new();
+    let mut steps = Vec::new();
+
+    let outcome = loop {
+        let footprint = direction.to_footprint();
+        let square = visited.entry(guard).or_insert_with(Footprint::empty);
+
+        if square.contains(footprint) {
+            break Outcome::Looped;
+        }
+
+        square.insert(footprint);
+        steps.push((guard, direction));
 
         let Some(next) = step(guard, direction, max) else {
-            break;
+            break Outcome::Exited;
         };
 
         if grid.contains(&next) {
-            direction = direction.turn();
+            direction = turn.apply(direction);
             continue;
         }
 
         guard = next;
-    }
+    };
 
-    visited.len()
+    GuardPath { steps, outcome }
 }
 
 fn new_obstruction_positions(s: &str) -> usize {
-    use Direction::*;
+    obstruction_loops(s).len()
+}
 
-    let (mut grid, max, mut guard) = parse(s);
+/// Every square where an obstruction causes the guard to loop,
+/// along with the cycle produced. The cycle lists each square where
+/// the guard turns and the direction it was facing when it arrived.
+fn obstruction_loops(s: &str) -> BTreeMap<Coord, Vec<Guard>> {
+    let (grid, max, (mut guard, mut direction)) = parse(s);
+    let mut jumps = JumpTable::new(&grid, max);
 
-    let mut direction = U;
     let mut visited = BTreeSet::new();
-    let mut possible_loops = 0;
+    let mut loops = BTreeMap::new();
 
     loop {
         visited.insert(guard);
@@ -63,42 +110,178 @@ fn new_obstruction_positions(s: &str) -> usize {
             continue;
         } else if !visited.contains(&next) {
             // But what if there _was_ an obstacle?
-            grid.insert(next);
-            if is_loop(&grid, guard, direction, max) {
-                possible_loops += 1;
+            if let Some(cycle) =
+                jumps.with_obstacle(next, |jumps| find_loop(jumps, guard, direction))
+            {
+                loops.insert(next, cycle);
             }
-            grid.remove(&next);
         }
 
         guard = next;
     }
 
-    possible_loops
+    loops
 }
 
-fn is_loop(grid: &Grid, mut guard: Coord, mut direction: Direction, max: Max) -> bool {
+/// Only the squares where the guard turns are recorded, so this
+/// takes time proportional to the number of turns, not steps.
+fn find_loop(jumps: &JumpTable, mut guard: Coord, mut direction: Direction) -> Option<Vec<Guard>> {
     let mut visited = BTreeMap::new();
+    let mut turns = Vec::new();
 
     loop {
+        let stop = jumps.jump(guard, direction)?;
+
         let footprint = direction.to_footprint();
-        let square = visited.entry(guard).or_insert_with(Footprint::empty);
+        let square = visited.entry(stop).or_insert_with(Footprint::empty);
 
         if square.contains(footprint) {
-            return true;
+            let start = turns
+                .iter()
+                .position(|&turn| turn == (stop, direction))
+                .expect("Repeated turn was not recorded");
+            turns.drain(..start);
+            return Some(turns);
         }
 
         square.insert(footprint);
+        turns.push((stop, direction));
 
-        let Some(next) = step(guard, direction, max) else {
-            return false;
+        guard = stop;
+        direction = direction.turn();
+    }
+}
+
+fn render_obstructions(s: &str) -> String {
+    let (grid, max, (guard, direction)) = parse(s);
+    let obstructions = obstruction_loops(s);
+
+    let mut out = String::new();
+
+    for y in 0..=max.1 {
+        for x in 0..=max.0 {
+            let coord = (x, y);
+
+            let c = if coord == guard {
+                direction.sigil()
+            } else if grid.contains(&coord) {
+                '#'
+            } else if obstructions.contains_key(&coord) {
+                'O'
+            } else {
+                '.'
+            };
+            out.push(c);
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// For every square and direction, where the guard stops before
+/// running into an obstacle. `None` means the guard walks off the
+/// grid.
+#[derive(Debug, Clone, PartialEq)]
+struct JumpTable {
+    max: Max,
+    blocked: Vec<bool>,
+    stops: Vec<[Option<Coord>; 4]>,
+}
+
+impl JumpTable {
+    fn new(grid: &Grid, max: Max) -> Self {
+        use Direction::*;
+
+        let n_squares = (max.0 + 1) * (max.1 + 1);
+        let mut this = Self {
+            max,
+            blocked: vec![false; n_squares],
+            stops: vec![[None; 4]; n_squares],
         };
 
-        if grid.contains(&next) {
-            direction = direction.turn();
-            continue;
+        for &coord in grid {
+            let idx = this.index(coord);
+            this.blocked[idx] = true;
         }
 
-        guard = next;
+        for direction in [U, R, D, L] {
+            // Start at the edge the guard would walk off of and work
+            // backwards, remembering the most recent obstacle.
+            let edge = (0..=max.1)
+                .flat_map(|y| (0..=max.0).map(move |x| (x, y)))
+                .filter(|&coord| step(coord, direction, max).is_none());
+
+            for coord in edge {
+                let mut stop = None;
+                let mut square = Some(coord);
+
+                while let Some(coord) = square {
+                    let idx = this.index(coord);
+                    let behind = step(coord, direction.reverse(), max);
+
+                    if this.blocked[idx] {
+                        stop = behind;
+                    } else {
+                        this.stops[idx][direction.index()] = stop;
+                    }
+
+                    square = behind;
+                }
+            }
+        }
+
+        this
+    }
+
+    fn index(&self, (x, y): Coord) -> usize {
+        y * (self.max.0 + 1) + x
+    }
+
+    fn jump(&self, coord: Coord, direction: Direction) -> Option<Coord> {
+        self.stops[self.index(coord)][direction.index()]
+    }
+
+    /// Temporarily adds an obstacle. Only the squares in the same row
+    /// and column that could reach the obstacle are updated; they are
+    /// restored afterwards.
+    fn with_obstacle<R>(&mut self, obstacle: Coord, f: impl FnOnce(&Self) -> R) -> R {
+        use Direction::*;
+
+        let obstacle_idx = self.index(obstacle);
+        assert!(!self.blocked[obstacle_idx], "Square is already blocked");
+
+        let mut patched = Vec::new();
+
+        for direction in [U, R, D, L] {
+            let stop = step(obstacle, direction.reverse(), self.max);
+            let mut square = stop;
+
+            while let Some(coord) = square {
+                let idx = self.index(coord);
+
+                if self.blocked[idx] {
+                    break;
+                }
+
+                let old = std::mem::replace(&mut self.stops[idx][direction.index()], stop);
+                patched.push((idx, direction, old));
+
+                square = step(coord, direction.reverse(), self.max);
+            }
+        }
+
+        self.blocked[obstacle_idx] = true;
+
+        let result = f(self);
+
+        self.blocked[obstacle_idx] = false;
+
+        for (idx, direction, old) in patched {
+            self.stops[idx][direction.index()] = old;
+        }
+
+        result
     }
 }
 
@@ -106,30 +289,43 @@ type Grid = BTreeSet<Coord>;
 
 type Coord = (usize, usize);
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 struct Max(usize, usize);
 
-fn parse(s: &str) -> (Grid, Max, Coord) {
+type Guard = (Coord, Direction);
+
+fn parse(s: &str) -> (Grid, Max, Guard) {
+    let (grid, max, guards) = parse_guards(s);
+
+    let [guard] = guards[..] else {
+        panic!("Expected exactly one guard, found {}", guards.len());
+    };
+
+    (grid, max, guard)
+}
+
+fn parse_guards(s: &str) -> (Grid, Max, Vec<Guard>) {
+    use Direction::*;
+
     let mut grid = BTreeSet::new();
     let mut max_x = 0;
     let mut max_y = 0;
-    let mut guard = None;
+    let mut guards = Vec::new();
 
     for (y, l) in s.lines().enumerate() {
         for (x, c) in l.chars().enumerate() {
             match c {
-                '^' => {
-                    assert!(guard.is_none());
-                    guard = Some((x, y));
-                }
-
                 '#' => {
                     grid.insert((x, y));
                 }
 
                 '.' => { /* no-op */ }
 
-                o => panic!("Unknown sigil {o}"),
+                o => {
+                    let direction = [U, R, D, L].into_iter().find(|d| d.sigil() == o);
+                    let direction = direction.unwrap_or_else(|| panic!("Unknown sigil {o}"));
+                    guards.push(((x, y), direction));
+                }
             }
 
             max_x = x;
@@ -138,11 +334,29 @@ fn parse(s: &str) -> (Grid, Max, Coord) {
         max_y = y;
     }
 
-    let guard = guard.expect("Did not find a guard");
-    (grid, Max(max_x, max_y), guard)
+    assert!(!guards.is_empty(), "Did not find a guard");
+    (grid, Max(max_x, max_y), guards)
+}
+
+/// What the guard does when running into an obstacle.
+#[derive(Debug, Copy, Clone)]
+enum Turn {
+    Left,
+    Right,
+    Reverse,
+}
+
+impl Turn {
+    fn apply(self, direction: Direction) -> Direction {
+        match self {
+            Turn::Left => direction.reverse().turn(),
+            Turn::Right => direction.turn(),
+            Turn::Reverse => direction.reverse(),
+        }
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum Direction {
     U,
     R,
@@ -162,6 +376,25 @@ impl Direction {
         }
     }
 
+    fn reverse(self) -> Self {
+        self.turn().turn()
+    }
+
+    fn sigil(self) -> char {
+        use Direction::*;
+
+        match self {
+            U => '^',
+            R => '>',
+            D => 'v',
+            L => '<',
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
     fn to_footprint(self) -> Footprint {
         use Direction::*;
 
@@ -238,4 +471,101 @@ mod test {
     fn example_positions() {
         assert_eq!(6, new_obstruction_positions(EXAMPLE));
     }
+
+    #[test]
+    fn example_obstruction_loops() {
+        use Direction::*;
+
+        let loops = obstruction_loops(EXAMPLE);
+
+        let positions = loops.keys().copied().collect::<Vec<_>>();
+        assert_eq!(
+            [(1, 8), (3, 6), (3, 8), (6, 7), (7, 7), (7, 9)],
+            &positions[..],
+        );
+
+        assert_eq!(
+            [((4, 6), L), ((4, 1), U), ((8, 1), R), ((8, 6), D)],
+            &loops[&(3, 6)][..],
+        );
+
+        assert_eq!(
+            render_obstructions(EXAMPLE),
+            "\
+....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#.O^.....
+......OO#.
+#O.O......
+......#O..
+",
+        );
+    }
+
+    #[test]
+    fn render_keeps_guard_direction() {
+        let map = EXAMPLE.replace('^', ">");
+        let rendered = render_obstructions(&map);
+
+        assert_eq!(
+            Some('>'),
+            rendered.lines().nth(6).and_then(|l| l.chars().nth(4))
+        );
+        assert!(!rendered.contains('^'));
+    }
+
+    #[test]
+    fn multiple_guards() {
+        use Direction::*;
+
+        // The example, with the obstruction that makes a loop and a
+        // second guard that walks off the grid.
+        let map = "\
+v...#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#.#^.....
+........#.
+#.........
+......#...";
+
+        let paths = guard_paths(map, Turn::Right);
+        let outcomes = paths.iter().map(|p| p.outcome).collect::<Vec<_>>();
+        assert_eq!([Outcome::Exited, Outcome::Looped], &outcomes[..]);
+        assert_eq!([((0, 6), D), ((0, 7), D), ((0, 7), L)], paths[0].steps[6..]);
+
+        let paths = guard_paths(map, Turn::Left);
+        assert_eq!(
+            [((0, 7), D), ((0, 7), R), ((1, 7), R)],
+            paths[0].steps[7..10],
+        );
+
+        let paths = guard_paths(map, Turn::Reverse);
+        assert_eq!(
+            [((0, 7), D), ((0, 7), U), ((0, 6), U)],
+            paths[0].steps[7..10],
+        );
+        assert_eq!(Outcome::Exited, paths[0].outcome);
+    }
+
+    #[test]
+    fn jump_table_obstacle_is_restored() {
+        let (grid, max, (guard, _)) = parse(EXAMPLE);
+        let mut jumps = JumpTable::new(&grid, max);
+        let original = jumps.clone();
+
+        assert_eq!(Some((4, 1)), jumps.jump(guard, Direction::U));
+
+        let patched = jumps.with_obstacle((4, 3), |jumps| jumps.jump(guard, Direction::U));
+        assert_eq!(Some((4, 4)), patched);
+
+        assert_eq!(original, jumps);
+    }
 }
@@ -1,5 +1,8 @@
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
 use itertools::Itertools;
-use std::iter;
+use std::{fmt, iter};
 
 const INPUT: &str = include_str!("../input.txt");
 
@@ -9,17 +12,21 @@ fn main() {
 }
 
 fn total_calibration(s: &str) -> u64 {
-    total_calibration_with_operators(s, &Op::LIMITED)
+    total_calibration_with_operators(s, LIMITED, Evaluation::LeftToRight)
 }
 
 fn total_calibration_concat(s: &str) -> u64 {
-    total_calibration_with_operators(s, &Op::ALL)
+    total_calibration_with_operators(s, ALL, Evaluation::LeftToRight)
 }
 
-fn total_calibration_with_operators(s: &str, op_choices: &[Op]) -> u64 {
+fn total_calibration_with_operators(
+    s: &str,
+    op_choices: &[&dyn Operator],
+    evaluation: Evaluation,
+) -> u64 {
     s.lines()
         .map(parse_line)
-        .filter(|(test, numbers)| test_with_operators(numbers, op_choices, *test))
+        .filter(|(test, numbers)| test_with_operators(numbers, op_choices, evaluation, *test))
         .map(|(test, _)| test)
         .sum()
 }
@@ -36,45 +43,434 @@ fn parse_line(l: &str) -> (u64, Vec<u64>) {
     (test, numbers)
 }
 
-fn test_with_operators(numbers: &[u64], op_choices: &[Op], test: u64) -> bool {
-    let (&head, tail) = numbers.split_first().expect("Need more than one number");
+fn test_with_operators(
+    numbers: &[u64],
+    op_choices: &[&dyn Operator],
+    evaluation: Evaluation,
+    test: u64,
+) -> bool {
+    solve(numbers, op_choices, evaluation, test).is_some()
+}
 
-    iter::repeat_n(op_choices, tail.len())
-        .multi_cartesian_product()
-        .any(|ops| {
-            let computed = ops
-                .into_iter()
-                .zip(tail)
-                .fold(head, |acc, (op, &n)| op.apply(acc, n));
+/// The first operator sequence that produces the test value.
+fn solve<'a>(
+    numbers: &[u64],
+    op_choices: &[&'a dyn Operator],
+    evaluation: Evaluation,
+    test: u64,
+) -> Option<Vec<&'a dyn Operator>> {
+    let solution = solutions(numbers, op_choices, evaluation, test, false).pop()?;
+
+    debug_assert_eq!(Some(test), evaluation.evaluate(numbers, &solution));
+    Some(solution)
+}
 
-            test == computed
-        })
+/// Every operator sequence that produces the test value.
+fn solve_all<'a>(
+    numbers: &[u64],
+    op_choices: &[&'a dyn Operator],
+    evaluation: Evaluation,
+    test: u64,
+) -> Vec<Vec<&'a dyn Operator>> {
+    solutions(numbers, op_choices, evaluation, test, true)
 }
 
-#[derive(Debug, Copy, Clone)]
-enum Op {
-    Add,
-    Mul,
-    Concat,
+fn solutions<'a>(
+    numbers: &[u64],
+    op_choices: &[&'a dyn Operator],
+    evaluation: Evaluation,
+    test: u64,
+    find_all: bool,
+) -> Vec<Vec<&'a dyn Operator>> {
+    let mut solutions = Vec::new();
+
+    let can_work_backwards = evaluation == Evaluation::LeftToRight
+        && op_choices.iter().all(|op| op.invertible().is_some());
+
+    if can_work_backwards {
+        solve_backwards(
+            numbers,
+            op_choices,
+            test,
+            &mut Vec::new(),
+            &mut solutions,
+            find_all,
+        );
+    } else {
+        let matching = solve_forwards(numbers, op_choices, evaluation, |v| v == test);
+
+        if find_all {
+            solutions.extend(matching);
+        } else {
+            solutions.extend(matching.take(1));
+        }
+    }
+
+    solutions
 }
 
-impl Op {
-    const ALL: [Self; 3] = [Op::Add, Op::Mul, Op::Concat];
-    const LIMITED: [Self; 2] = [Op::Add, Op::Mul];
+/// Starting from the test value, undo the last operation to find
+/// what the rest of the numbers must produce. Branches where the
+/// operation can't be undone are abandoned. Every operator must be
+/// [invertible](Operator::invertible).
+fn solve_backwards<'a>(
+    numbers: &[u64],
+    op_choices: &[&'a dyn Operator],
+    target: u64,
+    ops: &mut Vec<&'a dyn Operator>,
+    solutions: &mut Vec<Vec<&'a dyn Operator>>,
+    find_all: bool,
+) {
+    let (&last, rest) = numbers.split_last().expect("Need more than one number");
+
+    if rest.is_empty() {
+        if last == target {
+            // We built the operators from right to left
+            solutions.push(ops.iter().rev().copied().collect());
+        }
+        return;
+    }
+
+    for &op in op_choices {
+        let inverse = op.invertible().expect("Operator cannot be undone");
+        let Some(preimage) = inverse.unapply(target, last) else {
+            continue;
+        };
+
+        ops.push(op);
+        match preimage {
+            Preimage::Exactly(target) => {
+                solve_backwards(rest, op_choices, target, ops, solutions, find_all);
+            }
+
+            Preimage::Any => {
+                // Any prefix works, as long as it doesn't overflow
+                let prefixes = solve_forwards(rest, op_choices, Evaluation::LeftToRight, |_| true);
+                let found = prefixes.map(|mut prefix| {
+                    prefix.extend(ops.iter().rev());
+                    prefix
+                });
+
+                if find_all {
+                    solutions.extend(found);
+                } else {
+                    solutions.extend(found.take(1));
+                }
+            }
+        }
+        ops.pop();
+
+        if !find_all && !solutions.is_empty() {
+            return;
+        }
+    }
+}
+
+/// Tries every operator sequence, keeping those whose result is
+/// accepted.
+fn solve_forwards<'a>(
+    numbers: &[u64],
+    op_choices: &[&'a dyn Operator],
+    evaluation: Evaluation,
+    accept: impl Fn(u64) -> bool,
+) -> impl Iterator<Item = Vec<&'a dyn Operator>> {
+    let (_, tail) = numbers.split_first().expect("Need more than one number");
+
+    // With a single number, the only sequence is the empty one
+    let sequences = iter::repeat_n(op_choices, tail.len()).multi_cartesian_product();
+
+    sequences
+        .map(|ops| ops.into_iter().copied().collect::<Vec<_>>())
+        .filter(move |ops| evaluation.evaluate(numbers, ops).is_some_and(&accept))
+}
+
+fn format_expression(numbers: &[u64], ops: &[&dyn Operator]) -> String {
+    let (&head, tail) = numbers.split_first().expect("Need more than one number");
+
+    let mut s = head.to_string();
+    for (op, n) in ops.iter().zip(tail) {
+        s.push_str(&format!(" {op} {n}"));
+    }
+    s
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Evaluation {
+    /// How the elephants do it
+    LeftToRight,
+    /// Higher precedence operators are applied first. Operators of
+    /// equal precedence are applied left to right.
+    Precedence,
+}
+
+impl Evaluation {
+    /// Returns `None` if any intermediate value overflows or is
+    /// otherwise not representable.
+    fn evaluate(self, numbers: &[u64], ops: &[&dyn Operator]) -> Option<u64> {
+        let (&head, tail) = numbers.split_first().expect("Need more than one number");
+        assert_eq!(
+            tail.len(),
+            ops.len(),
+            "Need one operator between each number"
+        );
 
-    fn apply(self, l: u64, r: u64) -> u64 {
         match self {
-            Op::Add => l + r,
-            Op::Mul => l * r,
-            Op::Concat => {
-                let n_r_digits = r.ilog10() + 1;
-                let f = 10u64.pow(n_r_digits);
-                l * f + r
+            Evaluation::LeftToRight => ops
+                .iter()
+                .zip(tail)
+                .try_fold(head, |acc, (op, &n)| op.apply(acc, n)),
+
+            Evaluation::Precedence => {
+                let mut values = vec![head];
+                let mut pending = Vec::<&dyn Operator>::new();
+
+                fn reduce(values: &mut Vec<u64>, op: &dyn Operator) -> Option<()> {
+                    let r = values.pop().expect("Missing right-hand value");
+                    let l = values.pop().expect("Missing left-hand value");
+                    values.push(op.apply(l, r)?);
+                    Some(())
+                }
+
+                for (&op, &n) in ops.iter().zip(tail) {
+                    while let Some(&top) = pending.last() {
+                        if top.precedence() < op.precedence() {
+                            break;
+                        }
+                        pending.pop();
+                        reduce(&mut values, top)?;
+                    }
+
+                    pending.push(op);
+                    values.push(n);
+                }
+
+                while let Some(top) = pending.pop() {
+                    reduce(&mut values, top)?;
+                }
+
+                values.pop()
             }
         }
     }
 }
 
+const ALL: &[&dyn Operator] = &[&Add, &Mul, &Concat::DECIMAL];
+const LIMITED: &[&dyn Operator] = &[&Add, &Mul];
+
+/// The left-hand values that give a particular result.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Preimage {
+    Exactly(u64),
+    /// Every value does, such as when multiplying by zero
+    Any,
+}
+
+trait Operator: fmt::Display {
+    /// Returns `None` on overflow or when the result is otherwise
+    /// not representable.
+    fn apply(&self, l: u64, r: u64) -> Option<u64>;
+
+    /// Operators that can be undone let us work backwards from the
+    /// test value.
+    fn invertible(&self) -> Option<&dyn Invertible> {
+        None
+    }
+
+    /// Higher values bind more tightly.
+    fn precedence(&self) -> u8;
+}
+
+trait Invertible {
+    /// Finds every `l` such that `l op r == result`.
+    fn unapply(&self, result: u64, r: u64) -> Option<Preimage>;
+}
+
+struct Add;
+
+impl Operator for Add {
+    fn apply(&self, l: u64, r: u64) -> Option<u64> {
+        l.checked_add(r)
+    }
+
+    fn invertible(&self) -> Option<&dyn Invertible> {
+        Some(self)
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+}
+
+impl Invertible for Add {
+    fn unapply(&self, result: u64, r: u64) -> Option<Preimage> {
+        result.checked_sub(r).map(Preimage::Exactly)
+    }
+}
+
+impl fmt::Display for Add {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "+".fmt(f)
+    }
+}
+
+struct Sub;
+
+impl Operator for Sub {
+    fn apply(&self, l: u64, r: u64) -> Option<u64> {
+        l.checked_sub(r)
+    }
+
+    fn invertible(&self) -> Option<&dyn Invertible> {
+        Some(self)
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+}
+
+impl Invertible for Sub {
+    fn unapply(&self, result: u64, r: u64) -> Option<Preimage> {
+        result.checked_add(r).map(Preimage::Exactly)
+    }
+}
+
+impl fmt::Display for Sub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "-".fmt(f)
+    }
+}
+
+struct Mul;
+
+impl Operator for Mul {
+    fn apply(&self, l: u64, r: u64) -> Option<u64> {
+        l.checked_mul(r)
+    }
+
+    fn invertible(&self) -> Option<&dyn Invertible> {
+        Some(self)
+    }
+
+    fn precedence(&self) -> u8 {
+        3
+    }
+}
+
+impl Invertible for Mul {
+    fn unapply(&self, result: u64, r: u64) -> Option<Preimage> {
+        // Multiplying by zero loses the left-hand side
+        if r == 0 {
+            return (result == 0).then_some(Preimage::Any);
+        }
+        (result % r == 0).then_some(Preimage::Exactly(result / r))
+    }
+}
+
+impl fmt::Display for Mul {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "*".fmt(f)
+    }
+}
+
+/// Integer division, discarding the remainder. This can't be undone.
+struct Div;
+
+impl Operator for Div {
+    fn apply(&self, l: u64, r: u64) -> Option<u64> {
+        l.checked_div(r)
+    }
+
+    fn precedence(&self) -> u8 {
+        3
+    }
+}
+
+impl fmt::Display for Div {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "/".fmt(f)
+    }
+}
+
+struct Xor;
+
+impl Operator for Xor {
+    fn apply(&self, l: u64, r: u64) -> Option<u64> {
+        Some(l ^ r)
+    }
+
+    fn invertible(&self) -> Option<&dyn Invertible> {
+        Some(self)
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+}
+
+impl Invertible for Xor {
+    fn unapply(&self, result: u64, r: u64) -> Option<Preimage> {
+        Some(Preimage::Exactly(result ^ r))
+    }
+}
+
+impl fmt::Display for Xor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "^".fmt(f)
+    }
+}
+
+/// Appends the digits of the right-hand value, written in the given
+/// base. Binds more tightly than any arithmetic. There are no digits
+/// in bases below 2, so it never applies there.
+struct Concat(pub u64);
+
+impl Concat {
+    pub const DECIMAL: Self = Self(10);
+
+    /// The power of the base that shifts the left-hand value past
+    /// all the digits of `r`.
+    fn shift(&self, r: u64) -> Option<u64> {
+        let Self(base) = *self;
+        if base < 2 {
+            return None;
+        }
+        let n_r_digits = r.checked_ilog(base).unwrap_or(0) + 1;
+        base.checked_pow(n_r_digits)
+    }
+}
+
+impl Operator for Concat {
+    fn apply(&self, l: u64, r: u64) -> Option<u64> {
+        let f = self.shift(r)?;
+        l.checked_mul(f)?.checked_add(r)
+    }
+
+    fn invertible(&self) -> Option<&dyn Invertible> {
+        Some(self)
+    }
+
+    fn precedence(&self) -> u8 {
+        4
+    }
+}
+
+impl Invertible for Concat {
+    fn unapply(&self, result: u64, r: u64) -> Option<Preimage> {
+        let f = self.shift(r)?;
+        (result % f == r).then_some(Preimage::Exactly(result / f))
+    }
+}
+
+impl fmt::Display for Concat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            10 => "||".fmt(f),
+            base => write!(f, "||{base}"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -90,4 +486,109 @@ mod test {
     fn example_concat() {
         assert_eq!(11387, total_calibration_concat(EXAMPLE));
     }
+
+    #[test]
+    fn example_winning_expressions() {
+        use Evaluation::*;
+
+        let expression = |numbers: &[u64], op_choices, evaluation, test| {
+            solve(numbers, op_choices, evaluation, test).map(|ops| format_expression(numbers, &ops))
+        };
+
+        assert_eq!(
+            Some("10 * 19"),
+            expression(&[10, 19], LIMITED, LeftToRight, 190).as_deref(),
+        );
+        assert_eq!(None, expression(&[17, 8, 14], LIMITED, LeftToRight, 192));
+        assert_eq!(
+            Some("6 * 8 || 6 * 15"),
+            expression(&[6, 8, 6, 15], ALL, LeftToRight, 7290).as_deref(),
+        );
+
+        let mut all = solve_all(&[81, 40, 27], LIMITED, LeftToRight, 3267)
+            .into_iter()
+            .map(|ops| format_expression(&[81, 40, 27], &ops))
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(["81 * 40 + 27", "81 + 40 * 27"], &all[..]);
+    }
+
+    #[test]
+    fn custom_operators_and_precedence() {
+        use Evaluation::*;
+
+        let ops: &[&dyn Operator] = &[&Add, &Sub, &Mul, &Div, &Xor, &Concat(2)];
+
+        let all = solve_all(&[81, 40, 27], ops, Precedence, 3267);
+        let all = all
+            .iter()
+            .map(|ops| format_expression(&[81, 40, 27], ops))
+            .collect::<Vec<_>>();
+        assert_eq!(["81 * 40 + 27"], &all[..]);
+
+        assert_eq!(Some(4), Precedence.evaluate(&[10, 3, 2], &[&Sub, &Mul]));
+        assert_eq!(Some(14), LeftToRight.evaluate(&[10, 3, 2], &[&Sub, &Mul]));
+        assert_eq!(Some(5), LeftToRight.evaluate(&[2, 1], &[&Concat(2)]));
+        assert_eq!(Some(3), LeftToRight.evaluate(&[7, 2], &[&Div]));
+        assert_eq!(Some(6), LeftToRight.evaluate(&[5, 3], &[&Xor]));
+
+        let mut all = solve_all(&[9, 1, 3], &[&Sub, &Xor], LeftToRight, 5)
+            .into_iter()
+            .map(|ops| format_expression(&[9, 1, 3], &ops))
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(["9 - 1 - 3", "9 ^ 1 - 3"], &all[..]);
+    }
+
+    #[test]
+    fn multiplying_by_zero() {
+        use Evaluation::*;
+
+        assert!(test_with_operators(&[5, 0], LIMITED, LeftToRight, 0));
+        assert!(test_with_operators(&[3, 4, 0], LIMITED, LeftToRight, 0));
+        assert!(test_with_operators(&[3, 4, 0, 2], LIMITED, LeftToRight, 2));
+        assert!(!test_with_operators(&[3, 4, 0], LIMITED, LeftToRight, 1));
+
+        let mut all = solve_all(&[3, 4, 0, 2], LIMITED, LeftToRight, 2)
+            .into_iter()
+            .map(|ops| format_expression(&[3, 4, 0, 2], &ops))
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(["3 * 4 * 0 + 2", "3 + 4 * 0 + 2"], &all[..]);
+
+        // The part that gets multiplied away still has to fit
+        let numbers = [u64::MAX, 1, 0];
+        assert!(test_with_operators(&numbers, LIMITED, LeftToRight, 0));
+        let numbers = [u64::MAX, 2, 0];
+        assert!(!test_with_operators(&numbers, LIMITED, LeftToRight, 0));
+    }
+
+    #[test]
+    fn overflow_is_detected() {
+        use Evaluation::*;
+
+        assert_eq!(None, Mul.apply(u64::MAX, 2));
+        assert_eq!(None, Concat::DECIMAL.apply(u64::MAX / 10, 99));
+        assert_eq!(None, Sub.apply(1, 2));
+        assert_eq!(None, Div.apply(1, 0));
+
+        let numbers = [u64::MAX, 2, 3];
+        assert_eq!(None, LeftToRight.evaluate(&numbers, &[&Mul, &Add]));
+        assert!(!test_with_operators(&numbers, ALL, LeftToRight, 1));
+    }
+
+    #[test]
+    fn concat_needs_a_base() {
+        use Evaluation::*;
+
+        for base in [0, 1] {
+            let concat = Concat(base);
+            assert_eq!(None, concat.apply(12, 3));
+            assert_eq!(None, concat.unapply(123, 3));
+
+            let ops: &[&dyn Operator] = &[&Add, &concat];
+            assert!(test_with_operators(&[12, 3], ops, LeftToRight, 15));
+            assert!(!test_with_operators(&[12, 3], ops, LeftToRight, 123));
+        }
+    }
 }
@@ -1,3 +1,6 @@
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
 use itertools::Itertools;
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -11,82 +14,189 @@ fn main() {
 fn unique_antinode_locations(s: &str) -> usize {
     let (antennas, max) = parse(s);
 
-    let mut antinodes = BTreeSet::new();
+    let antinodes = antinodes(&antennas, &Area::rectangle(max), Model::Paired);
+    count_unique_locations(&antinodes)
+}
+
+fn unique_antinode_locations_resonant(s: &str) -> usize {
+    let (antennas, max) = parse(s);
+
+    let model = Model::Resonant { reduce_step: false };
+    let antinodes = antinodes(&antennas, &Area::rectangle(max), model);
+    count_unique_locations(&antinodes)
+}
+
+/// Stacked 2-D layers separated by blank lines. Each layer is one
+/// step along the Z axis.
+fn unique_antinode_locations_3d(s: &str, model: Model) -> usize {
+    let (antennas, max) = parse_layers(s);
+
+    let antinodes = antinodes(&antennas, &Area::rectangle(max), model);
+    count_unique_locations(&antinodes)
+}
+
+fn count_unique_locations<const N: usize>(antinodes: &[Antinode<N>]) -> usize {
+    let locations = antinodes.iter().map(|an| an.location);
+    locations.collect::<BTreeSet<_>>().len()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Model {
+    /// One antinode on the far side of each antenna.
+    Paired,
+
+    /// Every point in line with both antennas. When `reduce_step` is
+    /// set, the delta is divided by its gcd so that lattice points
+    /// between the antennas are also found.
+    Resonant { reduce_step: bool },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Antinode<const N: usize> {
+    pub location: Coord<N>,
+    pub frequency: char,
+    pub antennas: (Coord<N>, Coord<N>),
+}
 
-    for transmitters in antennas.values() {
+/// Every antinode, once per pair of antennas that produces it. The
+/// same location may be listed multiple times.
+fn antinodes<const N: usize>(antennas: &Grid<N>, area: &Area<N>, model: Model) -> Vec<Antinode<N>> {
+    let mut antinodes = Vec::new();
+
+    for (&frequency, transmitters) in antennas {
         // For each pair of transmitters
-        for (t1, t2) in transmitters.iter().tuple_combinations() {
-            // Find the delta of the positions
-            let dx = t1.0 - t2.0;
-            let dy = t1.1 - t2.1;
+        for (&t1, &t2) in transmitters.iter().tuple_combinations() {
+            let mut add = |location| {
+                if area.contains(location) {
+                    antinodes.push(Antinode {
+                        location,
+                        frequency,
+                        antennas: (t1, t2),
+                    });
+                }
+            };
 
-            // Use the delta to compute the potential antinode locations
-            let an1 = (t1.0 + dx, t1.1 + dy);
-            let an2 = (t2.0 - dx, t2.1 - dy);
+            // Find the delta of the positions
+            let delta = sub(t1, t2);
 
-            // eprintln!("{t1:?} <-> {t2:?} [({dx},{dy})]: {an1:?} & {an2:?}");
+            match model {
+                Model::Paired => {
+                    // Use the delta to compute the potential antinode locations
+                    add(plus(t1, delta));
+                    add(sub(t2, delta));
+                }
 
-            for an in [an1, an2] {
-                if max.is_in_bounds(an) {
-                    antinodes.insert(an);
+                Model::Resonant { reduce_step } => {
+                    let delta = if reduce_step {
+                        let d = delta.into_iter().fold(0, gcd);
+                        delta.map(|c| c / d)
+                    } else {
+                        delta
+                    };
+
+                    // Starting at a transmitter, walk the grid until
+                    // we fall off. Each location in the area is an
+                    // antinode. The area may have holes, so keep
+                    // walking through them.
+                    let mut current = t1;
+                    while area.max.is_in_bounds(current) {
+                        add(current);
+                        current = plus(current, delta);
+                    }
+
+                    // Same thing in the opposite direction.
+                    let mut current = sub(t1, delta);
+                    while area.max.is_in_bounds(current) {
+                        add(current);
+                        current = sub(current, delta);
+                    }
                 }
             }
         }
     }
 
-    antinodes.len()
+    antinodes
 }
 
-fn unique_antinode_locations_resonant(s: &str) -> usize {
-    let (antennas, max) = parse(s);
+fn plus<const N: usize>(a: Coord<N>, b: Coord<N>) -> Coord<N> {
+    std::array::from_fn(|i| a[i] + b[i])
+}
 
-    let mut antinodes = BTreeSet::new();
+fn sub<const N: usize>(a: Coord<N>, b: Coord<N>) -> Coord<N> {
+    std::array::from_fn(|i| a[i] - b[i])
+}
 
-    for transmitters in antennas.values() {
-        // For each pair of transmitters
-        for (&t1, &t2) in transmitters.iter().tuple_combinations() {
-            // Find the delta of the positions
-            let dx = t1.0 - t2.0;
-            let dy = t1.1 - t2.1;
+fn gcd(a: i32, b: i32) -> i32 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
 
-            // Starting at a transmitter, walk the grid until we fall
-            // off. Each location is an antinode.
-            let mut current = t1;
-            while max.is_in_bounds(current) {
-                antinodes.insert(current);
+/// `[x, y]` for a flat map, `[x, y, z]` for stacked layers.
+type Coord<const N: usize> = [i32; N];
 
-                current.0 += dx;
-                current.1 += dy;
-            }
+type Grid<const N: usize> = BTreeMap<char, Vec<Coord<N>>>;
 
-            // Same thing in the opposite direction.
-            let mut current = t1;
-            while max.is_in_bounds(current) {
-                antinodes.insert(current);
+#[derive(Copy, Clone)]
+struct Max<const N: usize>([i32; N]);
 
-                current.0 -= dx;
-                current.1 -= dy;
-            }
+impl<const N: usize> Max<N> {
+    fn is_in_bounds(self, c: Coord<N>) -> bool {
+        self.0.iter().zip(c).all(|(&max, c)| (0..=max).contains(&c))
+    }
+}
+
+/// The cells where antinodes may occur.
+struct Area<const N: usize> {
+    max: Max<N>,
+    mask: Option<BTreeSet<Coord<N>>>,
+}
+
+impl<const N: usize> Area<N> {
+    pub fn rectangle(max: Max<N>) -> Self {
+        Self { max, mask: None }
+    }
+
+    /// Only the cells in the mask are valid.
+    pub fn with_mask(self, mask: BTreeSet<Coord<N>>) -> Self {
+        Self {
+            mask: Some(mask),
+            ..self
         }
     }
 
-    antinodes.len()
+    fn contains(&self, c: Coord<N>) -> bool {
+        self.max.is_in_bounds(c) && self.mask.as_ref().is_none_or(|m| m.contains(&c))
+    }
 }
 
-type Coord = (i32, i32);
+/// A grid the same shape as the antenna map where `#` marks a valid
+/// cell.
+fn parse_mask(s: &str) -> BTreeSet<Coord<2>> {
+    let mut mask = BTreeSet::new();
 
-type Grid = BTreeMap<char, Vec<Coord>>;
+    for (y, l) in s.lines().enumerate() {
+        let y = i32::try_from(y).expect("Y out of range");
 
-#[derive(Copy, Clone)]
-struct Max(i32, i32);
+        for (x, c) in l.chars().enumerate() {
+            let x = i32::try_from(x).expect("X out of range");
 
-impl Max {
-    fn is_in_bounds(self, c: Coord) -> bool {
-        (0..=self.0).contains(&c.0) && (0..=self.1).contains(&c.1)
+            match c {
+                '#' => {
+                    mask.insert([x, y]);
+                }
+                '.' => { /* no-op */ }
+                o => panic!("Unknown mask sigil {o}"),
+            }
+        }
     }
+
+    mask
 }
 
-fn parse(s: &str) -> (Grid, Max) {
+fn parse(s: &str) -> (Grid<2>, Max<2>) {
     let mut max_x = 0;
     let mut max_y = 0;
 
@@ -104,11 +214,47 @@ fn parse(s: &str) -> (Grid, Max) {
                 continue;
             }
 
-            antennas.entry(c).or_insert_with(Vec::new).push((x, y));
+            antennas.entry(c).or_insert_with(Vec::new).push([x, y]);
         }
     }
 
-    (antennas, Max(max_x, max_y))
+    (antennas, Max([max_x, max_y]))
+}
+
+fn parse_layers(s: &str) -> (Grid<3>, Max<3>) {
+    let mut max_xy = None;
+    let mut max_z = 0;
+
+    let mut antennas = BTreeMap::new();
+
+    for (z, layer) in blank_line_groups(s).into_iter().enumerate() {
+        let z = i32::try_from(z).expect("Z out of range");
+        max_z = z;
+
+        let (layer_antennas, Max(layer_max)) = parse(&layer.join("\n"));
+
+        let max = *max_xy.get_or_insert(layer_max);
+        assert_eq!(max, layer_max, "Layer {z} is a different size");
+
+        for (c, coords) in layer_antennas {
+            let coords = coords.into_iter().map(|[x, y]| [x, y, z]);
+            antennas.entry(c).or_insert_with(Vec::new).extend(coords);
+        }
+    }
+
+    let [max_x, max_y] = max_xy.expect("No layers");
+    (antennas, Max([max_x, max_y, max_z]))
+}
+
+/// The lines between blank lines, with either LF or CRLF endings.
+fn blank_line_groups(s: &str) -> Vec<Vec<&str>> {
+    let lines = s.lines().collect::<Vec<_>>();
+
+    lines
+        .split(|l| l.trim_end().is_empty())
+        .filter(|g| !g.is_empty())
+        .map(<[_]>::to_vec)
+        .collect()
 }
 
 #[cfg(test)]
@@ -126,4 +272,78 @@ mod test {
     fn example_resonance() {
         assert_eq!(34, unique_antinode_locations_resonant(EXAMPLE));
     }
+
+    #[test]
+    fn reduced_step() {
+        let (antennas, max) = parse(
+            "\
+a....
+.....
+..a..
+.....
+.....",
+        );
+        let area = Area::rectangle(max);
+
+        let raw = antinodes(&antennas, &area, Model::Resonant { reduce_step: false });
+        assert_eq!(3, count_unique_locations(&raw));
+
+        let reduced = antinodes(&antennas, &area, Model::Resonant { reduce_step: true });
+        assert_eq!(5, count_unique_locations(&reduced));
+        assert!(reduced.contains(&Antinode {
+            location: [1, 1],
+            frequency: 'a',
+            antennas: ([0, 0], [2, 2]),
+        }));
+    }
+
+    #[test]
+    fn example_masked() {
+        let (antennas, max) = parse(EXAMPLE);
+
+        // Only the top half of the map
+        let mask = parse_mask(&"############\n".repeat(6));
+        let area = Area::rectangle(max).with_mask(mask);
+
+        let antinodes = antinodes(&antennas, &area, Model::Paired);
+        assert!(antinodes.iter().all(|an| an.location[1] < 6));
+        assert_eq!(9, count_unique_locations(&antinodes));
+
+        let tagged = antinodes.iter().filter(|an| an.frequency == 'A');
+        let pairs = tagged.map(|an| an.antennas).collect::<Vec<_>>();
+        assert_eq!([([6, 5], [8, 8]), ([6, 5], [9, 9])], &pairs[..]);
+    }
+
+    #[test]
+    fn layered() {
+        let layers = "\
+a....
+.....
+.....
+
+.....
+.a...
+.....
+
+.....
+.....
+.....";
+
+        let (antennas, max) = parse_layers(layers);
+        assert_eq!(vec![[0, 0, 0], [1, 1, 1]], antennas[&'a']);
+        assert_eq!([4, 2, 2], max.0);
+
+        assert_eq!(1, unique_antinode_locations_3d(layers, Model::Paired));
+
+        let resonant = Model::Resonant { reduce_step: false };
+        assert_eq!(3, unique_antinode_locations_3d(layers, resonant));
+
+        let crlf = format!("{layers}\n").replace('\n', "\r\n");
+        let (crlf_antennas, crlf_max) = parse_layers(&crlf);
+        assert_eq!(antennas, crlf_antennas);
+        assert_eq!(max.0, crlf_max.0);
+
+        // A flat map is a single layer
+        assert_eq!(14, unique_antinode_locations_3d(EXAMPLE, Model::Paired));
+    }
 }
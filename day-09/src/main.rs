@@ -1,4 +1,11 @@
-use std::{cmp::Ordering, mem};
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    mem,
+    ops::Range,
+};
 
 const INPUT: &str = include_str!("../input.txt");
 
@@ -8,126 +15,137 @@ fn main() {
 }
 
 fn filesystem_checksum(s: &str) -> u64 {
-    let mut disk = parse(s);
+    let mut disk = Disk::new(&parse(s));
+    disk.compact_blocks();
+    checksum(&disk.to_contents())
+}
 
-    loop {
-        // dump(&disk);
+fn filesystem_checksum_whole_file(s: &str) -> u64 {
+    let mut disk = Disk::new(&parse(s));
+    disk.compact_whole_files();
+    checksum(&disk.to_contents())
+}
 
-        let mut src = match disk.pop() {
-            Some(Content::Free { .. }) => {
-                // ignoring free space
-                continue;
-            }
-            Some(c) => {
-                if c.is_empty() {
-                    // ignoring empty contents
-                    continue;
-                }
+fn parse(s: &str) -> Vec<Content> {
+    let mut id = 0;
+
+    s.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let len = c.to_digit(10).expect("Not a valid digit").into();
+
+            if i % 2 == 0 {
+                let c = Content::File { len, id };
+                id += 1;
                 c
+            } else {
+                Content::Free { len }
             }
-            None => panic!("Ran out of contents"),
-        };
+        })
+        .collect()
+}
 
-        let dst_idx = disk.iter().position(|c| matches!(c, Content::Free { .. }));
+/// The inverse of [`parse`]. Returns `None` if the disk can't be
+/// represented: file IDs must be sequential from zero and every
+/// length must be a single digit.
+fn encode_dense(disk: &[Content]) -> Option<String> {
+    let mut s = String::new();
+    let mut next_id = 0;
+    // Free space seen since the most recent file
+    let mut free = None;
 
-        // No more free space, done compacting
-        let Some(dst_idx) = dst_idx else {
-            disk.push(src);
-            break;
-        };
-        let dst = &mut disk[dst_idx];
+    let digit = |len: u64| char::from_digit(len.try_into().ok()?, 10);
 
-        match dst.len().cmp(&src.len()) {
-            Ordering::Equal => {
-                *dst = src;
-            }
+    for c in disk {
+        match *c {
+            Content::File { len, id } => {
+                if id != next_id {
+                    return None;
+                }
 
-            Ordering::Less => {
-                let leftover = src.split_at(dst.len());
-                *dst = src;
-                // Put the remainder back at the end
-                disk.push(leftover);
+                if next_id != 0 {
+                    s.push(digit(free.unwrap_or(0))?);
+                }
+                s.push(digit(len)?);
+
+                next_id += 1;
+                free = None;
             }
 
-            Ordering::Greater => {
-                let leftover = dst.split_at(src.len());
-                *dst = src;
-                // Put the remainder back after where it started
-                disk.insert(dst_idx + 1, leftover);
+            Content::Free { len } => {
+                // The first entry is always a file
+                if next_id == 0 {
+                    return None;
+                }
+
+                *free.get_or_insert(0) += len;
             }
         }
     }
 
-    checksum(&disk)
+    if let Some(free) = free {
+        s.push(digit(free)?);
+    }
+
+    Some(s)
 }
 
-fn filesystem_checksum_whole_file(s: &str) -> u64 {
-    let mut disk = parse(s);
-
-    let id = disk
-        .iter()
-        .rev()
-        .find_map(Content::to_file_id)
-        .expect("No files present");
-
-    for id in (0..=id).rev() {
-        // dump(&disk);
-
-        let src_idx = disk
-            .iter()
-            .rposition(|c| c.to_file_id() == Some(id))
-            .expect("Source file not found");
-        let src = &disk[src_idx];
-
-        let dst_idx = disk
-            .iter()
-            .position(|c| c.free_space().is_some_and(|f| f >= src.len()));
-        if let Some(dst_idx) = dst_idx {
-            // Only move to the left
-            if dst_idx < src_idx {
-                let src = disk[src_idx].take();
-
-                let dst = &mut disk[dst_idx];
-                let leftover = dst.split_at(src.len());
-                *dst = src;
-                disk.insert(dst_idx + 1, leftover);
-            }
-        }
+/// One space-separated entry per block: the file ID, or `.` for
+/// free space.
+fn encode_blocks(disk: &[Content]) -> String {
+    let mut blocks = Vec::new();
 
-        // Coalesce free space
-        {
-            let mut i = 0;
-            while i + 1 < disk.len() {
-                if disk[i].is_free() && disk[i + 1].is_free() {
-                    let b = disk.remove(i + 1);
-                    disk[i].absorb(b);
-                } else {
-                    i += 1;
-                }
-            }
+    for c in disk {
+        let block = match *c {
+            Content::File { id, .. } => id.to_string(),
+            Content::Free { .. } => ".".to_string(),
+        };
+
+        for _ in 0..c.len() {
+            blocks.push(block.clone());
         }
     }
 
-    checksum(&disk)
+    blocks.join(" ")
 }
 
-fn parse(s: &str) -> Vec<Content> {
-    let mut id = 0;
+/// The inverse of [`encode_blocks`]. Adjacent blocks with the same
+/// ID are combined; empty entries can't be recovered.
+fn decode_blocks(s: &str) -> Vec<Content> {
+    collect_blocks(s.split_whitespace())
+}
 
-    s.chars()
-        .enumerate()
-        .map(|(i, c)| {
-            let len = c.to_digit(10).expect("Not a valid digit").into();
+/// The puzzle's block notation without spaces, where every ID is a
+/// single digit.
+fn decode_block_digits(s: &str) -> Vec<Content> {
+    let s = s.trim();
+    collect_blocks(s.char_indices().map(|(i, c)| &s[i..][..c.len_utf8()]))
+}
 
-            if i % 2 == 0 {
-                let c = Content::File { len, id };
-                id += 1;
-                c
-            } else {
-                Content::Free { len }
+fn collect_blocks<'a>(blocks: impl Iterator<Item = &'a str>) -> Vec<Content> {
+    let mut disk = Vec::<Content>::new();
+
+    for block in blocks {
+        let next = match block {
+            "." => Content::Free { len: 1 },
+            id => {
+                let id = id.parse().expect("Not a valid file ID");
+                Content::File { len: 1, id }
             }
-        })
-        .collect()
+        };
+
+        match (disk.last_mut(), next) {
+            (Some(Content::Free { len }), Content::Free { .. }) => *len += 1,
+            (Some(Content::File { len, id }), Content::File { id: next_id, .. })
+                if *id == next_id =>
+            {
+                *len += 1
+            }
+            (_, next) => disk.push(next),
+        }
+    }
+
+    disk
 }
 
 fn checksum(disk: &[Content]) -> u64 {
@@ -145,7 +163,7 @@ fn checksum(disk: &[Content]) -> u64 {
         .sum()
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum Content {
     File { len: u64, id: u64 },
 
@@ -153,68 +171,439 @@ enum Content {
 }
 
 impl Content {
-    fn is_empty(&self) -> bool {
-        self.len() == 0
-    }
-
     fn len(&self) -> u64 {
         match *self {
             Content::File { len, .. } | Content::Free { len } => len,
         }
     }
+}
 
-    fn split_at(&mut self, part_len: u64) -> Self {
-        assert!(self.len() >= part_len, "{} >= {}", self.len(), part_len);
-        match self {
-            Content::File { len, id } => {
-                let leftover = Content::File {
-                    len: *len - part_len,
-                    id: *id,
-                };
-                *len = part_len;
-                leftover
+/// The disk as positioned extents, so that compaction doesn't need
+/// to shuffle a `Vec<Content>` around.
+#[derive(Debug)]
+struct Disk {
+    /// Ordered by position. Compacting individual blocks may split a
+    /// file into multiple extents.
+    files: Vec<Extent>,
+    free: FreeList,
+    len: u64,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Extent {
+    start: u64,
+    len: u64,
+    id: u64,
+}
+
+impl Disk {
+    pub fn new(contents: &[Content]) -> Self {
+        let mut files = Vec::new();
+        let len = contents.iter().map(Content::len).sum();
+        let mut free = FreeList::new(slot_starts(contents));
+        let mut start = 0;
+
+        for c in contents {
+            match *c {
+                Content::File { len, id } => {
+                    if len != 0 {
+                        files.push(Extent { start, len, id });
+                    }
+                }
+                Content::Free { len } => free.release(start, len),
             }
-            Content::Free { len } => {
-                let leftover = Content::Free {
-                    len: *len - part_len,
-                };
-                *len = part_len;
-                leftover
+            start += c.len();
+        }
+
+        Self { files, free, len }
+    }
+
+    pub fn compact_blocks(&mut self) {
+        let mut files = mem::take(&mut self.files);
+        let mut moved = Vec::new();
+
+        while let Some(mut src) = files.pop() {
+            // No more free space to the left, done compacting
+            let Some((dst, free_len)) = self.free.first().filter(|&(dst, _)| dst < src.start)
+            else {
+                files.push(src);
+                break;
+            };
+
+            // Move as many blocks from the end of the file as will fit
+            let n = free_len.min(src.len);
+            src.len -= n;
+
+            self.free.allocate(dst, n);
+            self.free.release(src.start + src.len, n);
+            moved.push(Extent {
+                start: dst,
+                len: n,
+                id: src.id,
+            });
+
+            if src.len != 0 {
+                files.push(src);
             }
         }
+
+        files.extend(moved);
+        files.sort_by_key(|e| e.start);
+        self.files = files;
     }
 
-    fn take(&mut self) -> Self {
-        let empty = Content::Free { len: self.len() };
-        mem::replace(self, empty)
+    pub fn compact_whole_files(&mut self) {
+        self.compact_whole_files_with(Fit::First, false);
     }
 
-    fn free_space(&self) -> Option<u64> {
-        match *self {
-            Content::File { .. } => None,
-            Content::Free { len } => Some(len),
+    /// Moves each file at most once, in order of decreasing ID. A file
+    /// split into several extents is moved as a whole, rejoining it.
+    /// Unless `allow_right` is set, files only move towards the start
+    /// of the disk, judged by their first extent.
+    pub fn compact_whole_files_with(&mut self, mut fit: Fit, allow_right: bool) -> Metrics {
+        let mut files_moved = 0;
+
+        let mut by_id = BTreeMap::<_, Vec<_>>::new();
+        for e in mem::take(&mut self.files) {
+            by_id.entry(e.id).or_default().push(e);
+        }
+
+        for (&id, extents) in by_id.iter_mut().rev() {
+            let start = extents[0].start;
+            let len = extents.iter().map(|e| e.len).sum();
+            let limit = if allow_right { u64::MAX } else { start };
+
+            let Some(dst) = self.free.find(fit, len, limit) else {
+                continue;
+            };
+
+            self.free.allocate(dst, len);
+            for e in extents.iter() {
+                self.free.release(e.start, e.len);
+            }
+            *extents = vec![Extent {
+                start: dst,
+                len,
+                id,
+            }];
+
+            files_moved += 1;
+
+            if let Fit::Next { cursor } = &mut fit {
+                *cursor = dst + len;
+            }
+        }
+
+        self.files = by_id.into_values().flatten().collect();
+        self.files.sort_by_key(|e| e.start);
+
+        Metrics {
+            free_extents: self.free.by_start.len(),
+            largest_free_extent: self.free.largest(),
+            files_moved,
         }
     }
 
-    fn to_file_id(&self) -> Option<u64> {
-        match *self {
-            Content::File { id, .. } => Some(id),
-            Content::Free { .. } => None,
+    pub fn to_contents(&self) -> Vec<Content> {
+        let mut contents = Vec::new();
+        let mut position = 0;
+
+        for e in &self.files {
+            if e.start > position {
+                let len = e.start - position;
+                contents.push(Content::Free { len });
+            }
+
+            contents.push(Content::File {
+                len: e.len,
+                id: e.id,
+            });
+            position = e.start + e.len;
+        }
+
+        if self.len > position {
+            let len = self.len - position;
+            contents.push(Content::Free { len });
         }
+
+        contents
     }
+}
+
+/// Where each entry of the disk map begins, skipping empty entries.
+fn slot_starts(contents: &[Content]) -> Vec<u64> {
+    let mut starts = vec![0];
+    let mut start = 0;
 
-    fn is_free(&self) -> bool {
-        match self {
-            Content::File { .. } => false,
-            Content::Free { .. } => true,
+    for c in contents {
+        start += c.len();
+        if c.len() != 0 {
+            starts.push(start);
         }
     }
 
-    fn absorb(&mut self, other: Content) {
-        match self {
-            Content::File { .. } => panic!("Can't absorb into a file"),
-            Content::Free { len } => *len += other.len(),
+    // The last start is the end of the disk
+    starts.pop();
+    starts
+}
+
+/// How to pick the free extent a file moves to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Fit {
+    /// The leftmost extent that fits.
+    First,
+    /// The smallest extent that fits, leftmost on ties.
+    Best,
+    /// The largest extent, leftmost on ties.
+    Worst,
+    /// The first extent that fits at or after `cursor`, wrapping
+    /// around to the start of the disk. Compaction moves the cursor
+    /// to the end of each file it moves.
+    Next { cursor: u64 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Metrics {
+    pub free_extents: usize,
+    pub largest_free_extent: u64,
+    pub files_moved: usize,
+}
+
+/// Free extents indexed by position, by length and by the longest
+/// extent in any range of slots, so that finding space for a file
+/// doesn't require scanning the disk.
+///
+/// A slot is the span of one entry of the original disk map. The
+/// index grows with the number of entries rather than blocks.
+#[derive(Debug)]
+struct FreeList {
+    by_start: BTreeMap<u64, u64>,
+    by_len: BTreeMap<u64, BTreeSet<u64>>,
+    /// Where each slot begins, in increasing order. The first is zero.
+    slots: Vec<u64>,
+    /// The longest free extent starting in each slot
+    longest: MaxTree,
+}
+
+impl FreeList {
+    fn new(slots: Vec<u64>) -> Self {
+        assert_eq!(Some(&0), slots.first(), "Slots must start at zero");
+
+        Self {
+            by_start: BTreeMap::new(),
+            by_len: BTreeMap::new(),
+            longest: MaxTree::new(slots.len()),
+            slots,
+        }
+    }
+
+    /// The slot containing `position`.
+    fn slot(&self, position: u64) -> usize {
+        self.slots.partition_point(|&s| s <= position) - 1
+    }
+
+    /// The positions covered by `slot`.
+    fn slot_span(&self, slot: usize) -> Range<u64> {
+        let end = self.slots.get(slot + 1).copied().unwrap_or(u64::MAX);
+        self.slots[slot]..end
+    }
+
+    /// The leftmost extent of at least `len` blocks that starts in
+    /// the range.
+    fn leftmost(&self, range: Range<u64>, len: u64) -> Option<u64> {
+        if range.is_empty() {
+            return None;
+        }
+
+        let mut slots = self.slot(range.start)..self.slot(range.end - 1) + 1;
+
+        // Only the slots at either end can hold extents outside the
+        // range, so this retries at most twice
+        while let Some(slot) = self.longest.leftmost(slots.clone(), len) {
+            let span = self.slot_span(slot);
+            let span = span.start.max(range.start)..span.end.min(range.end);
+
+            let found = self.by_start.range(span).find(|&(_, &l)| l >= len);
+            if let Some((&start, _)) = found {
+                return Some(start);
+            }
+
+            slots.start = slot + 1;
+        }
+
+        None
+    }
+
+    /// Recomputes the tree entry for the slot containing `position`.
+    fn update_slot(&mut self, position: u64) {
+        let slot = self.slot(position);
+        let span = self.slot_span(slot);
+        let longest = self.by_start.range(span).map(|(_, &l)| l).max();
+        self.longest.set(slot, longest.unwrap_or(0));
+    }
+
+    /// The leftmost free extent.
+    fn first(&self) -> Option<(u64, u64)> {
+        self.by_start.first_key_value().map(|(&s, &l)| (s, l))
+    }
+
+    /// The start of a free extent with room for `len` blocks that
+    /// begins before `limit`.
+    fn find(&self, fit: Fit, len: u64, limit: u64) -> Option<u64> {
+        match fit {
+            Fit::First => self.leftmost(0..limit, len),
+            // Checks one extent per distinct length
+            Fit::Best => self
+                .by_len
+                .range(len..)
+                .find_map(|(_, starts)| starts.range(0..limit).next().copied()),
+            Fit::Worst => self
+                .by_len
+                .range(len..)
+                .rev()
+                .find_map(|(_, starts)| starts.range(0..limit).next().copied()),
+            Fit::Next { cursor } => {
+                let cursor = cursor.min(limit);
+                let after = self.leftmost(cursor..limit, len);
+                // Wrap around to the start of the disk
+                after.or_else(|| self.leftmost(0..cursor, len))
+            }
+        }
+    }
+
+    fn largest(&self) -> u64 {
+        self.longest.max(0..self.slots.len())
+    }
+
+    fn insert(&mut self, start: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+
+        self.by_start.insert(start, len);
+        self.by_len.entry(len).or_default().insert(start);
+        self.update_slot(start);
+    }
+
+    fn remove(&mut self, start: u64) -> u64 {
+        let len = self.by_start.remove(&start).expect("Not a free extent");
+
+        let starts = self.by_len.get_mut(&len).expect("Index out of sync");
+        starts.remove(&start);
+        if starts.is_empty() {
+            self.by_len.remove(&len);
+        }
+        self.update_slot(start);
+
+        len
+    }
+
+    /// Uses the first `len` blocks of the free extent at `start`.
+    fn allocate(&mut self, start: u64, len: u64) {
+        let free_len = self.remove(start);
+        assert!(free_len >= len, "{free_len} >= {len}");
+        self.insert(start + len, free_len - len);
+    }
+
+    /// Returns blocks to the free list, coalescing with adjacent
+    /// free extents.
+    fn release(&mut self, mut start: u64, mut len: u64) {
+        if len == 0 {
+            return;
+        }
+
+        if let Some((&prev_start, &prev_len)) = self.by_start.range(..start).next_back() {
+            if prev_start + prev_len == start {
+                self.remove(prev_start);
+                start = prev_start;
+                len += prev_len;
+            }
+        }
+
+        if self.by_start.contains_key(&(start + len)) {
+            len += self.remove(start + len);
+        }
+
+        self.insert(start, len);
+    }
+}
+
+/// A segment tree holding a value for each of `len` slots.
+#[derive(Debug)]
+struct MaxTree {
+    /// Heap-ordered: node `i` covers its children `2i` and `2i + 1`,
+    /// leaves start at `leaves`.
+    nodes: Vec<u64>,
+    leaves: usize,
+}
+
+impl MaxTree {
+    fn new(len: usize) -> Self {
+        let leaves = len.next_power_of_two();
+
+        Self {
+            nodes: vec![0; 2 * leaves],
+            leaves,
+        }
+    }
+
+    fn set(&mut self, slot: usize, value: u64) {
+        let mut i = self.leaves + slot;
+        self.nodes[i] = value;
+
+        while i > 1 {
+            i /= 2;
+            self.nodes[i] = self.nodes[2 * i].max(self.nodes[2 * i + 1]);
+        }
+    }
+
+    /// The largest value in any slot in the range.
+    fn max(&self, range: Range<usize>) -> u64 {
+        self.max_in(1, 0..self.leaves, &self.clamp(range))
+    }
+
+    fn max_in(&self, node: usize, span: Range<usize>, range: &Range<usize>) -> u64 {
+        if span.end <= range.start || range.end <= span.start {
+            return 0;
         }
+        if range.start <= span.start && span.end <= range.end {
+            return self.nodes[node];
+        }
+
+        let mid = span.start + (span.end - span.start) / 2;
+        let left = self.max_in(2 * node, span.start..mid, range);
+        let right = self.max_in(2 * node + 1, mid..span.end, range);
+        left.max(right)
+    }
+
+    /// The first slot in the range with a value of at least
+    /// `at_least`. Zero never matches.
+    fn leftmost(&self, range: Range<usize>, at_least: u64) -> Option<usize> {
+        let at_least = at_least.max(1);
+        self.leftmost_in(1, 0..self.leaves, &self.clamp(range), at_least)
+    }
+
+    fn leftmost_in(
+        &self,
+        node: usize,
+        span: Range<usize>,
+        range: &Range<usize>,
+        at_least: u64,
+    ) -> Option<usize> {
+        if span.end <= range.start || range.end <= span.start || self.nodes[node] < at_least {
+            return None;
+        }
+        if span.len() == 1 {
+            return Some(span.start);
+        }
+
+        let mid = span.start + (span.end - span.start) / 2;
+        self.leftmost_in(2 * node, span.start..mid, range, at_least)
+            .or_else(|| self.leftmost_in(2 * node + 1, mid..span.end, range, at_least))
+    }
+
+    fn clamp(&self, range: Range<usize>) -> Range<usize> {
+        range.start.min(self.leaves)..range.end.min(self.leaves)
     }
 }
 
@@ -257,4 +646,156 @@ mod test {
     fn example_whole_file() {
         assert_eq!(2858, filesystem_checksum_whole_file(EXAMPLE));
     }
+
+    #[test]
+    fn example_round_trip() {
+        let disk = parse(EXAMPLE);
+        assert_eq!(Some(EXAMPLE), encode_dense(&disk).as_deref());
+
+        let blocks = encode_blocks(&disk);
+        assert!(blocks.starts_with("0 0 . . . 1 1 1 . . . 2"));
+
+        // Empty entries don't have any blocks
+        let non_empty = disk.into_iter().filter(|c| c.len() != 0);
+        assert_eq!(non_empty.collect::<Vec<_>>(), decode_blocks(&blocks));
+
+        assert_eq!(Some("12345"), encode_dense(&parse("12345")).as_deref());
+        assert_eq!(Some("10101"), encode_dense(&parse("10101")).as_deref());
+    }
+
+    #[test]
+    fn example_compacted_blocks() {
+        let mut disk = Disk::new(&parse(EXAMPLE));
+        disk.compact_whole_files();
+        let disk = disk.to_contents();
+
+        // IDs are no longer in order
+        assert_eq!(None, encode_dense(&disk));
+
+        let expected = "00992111777.44.333....5555.6666.....8888..";
+        assert_eq!(disk, decode_block_digits(expected));
+        assert_eq!(disk, decode_blocks(&encode_blocks(&disk)));
+        assert_eq!(
+            1,
+            encode_blocks(&decode_blocks("10 10 11"))
+                .matches("11")
+                .count()
+        );
+
+        // Multi-digit IDs aren't mistaken for the unspaced notation
+        let disk = vec![Content::File { len: 1, id: 10 }];
+        assert_eq!(disk, decode_blocks(&encode_blocks(&disk)));
+        let file = |id| Content::File { len: 1, id };
+        assert_eq!(vec![file(1), file(0)], decode_block_digits("10"));
+    }
+
+    #[test]
+    fn whole_files_by_id() {
+        let compact = |s| {
+            let mut disk = Disk::new(&decode_block_digits(s));
+            disk.compact_whole_files();
+            encode_blocks(&disk.to_contents()).replace(' ', "")
+        };
+
+        // File 1 is split in two and moves as one piece, after file 2
+        assert_eq!("0022111....", compact("00...2211.1"));
+
+        // Position order isn't ID order
+        assert_eq!("021..", compact("0.2.1"));
+    }
+
+    #[test]
+    fn example_fits() {
+        let run = |s, fit, allow_right| {
+            let mut disk = Disk::new(&parse(s));
+            let metrics = disk.compact_whole_files_with(fit, allow_right);
+            (checksum(&disk.to_contents()), metrics)
+        };
+
+        let metrics = |free_extents, largest_free_extent, files_moved| Metrics {
+            free_extents,
+            largest_free_extent,
+            files_moved,
+        };
+
+        assert_eq!((2858, metrics(6, 5, 4)), run(EXAMPLE, Fit::First, false));
+
+        // 0..1.2...3
+        let s = "1211131";
+        assert_eq!((10, metrics(1, 6, 2)), run(s, Fit::First, false));
+        assert_eq!((16, metrics(2, 5, 3)), run(s, Fit::Best, false));
+        assert_eq!((22, metrics(2, 3, 3)), run(s, Fit::Worst, false));
+        let next = Fit::Next { cursor: 0 };
+        assert_eq!((10, metrics(1, 6, 2)), run(s, next, false));
+        assert_eq!((11, metrics(2, 5, 4)), run(s, Fit::First, true));
+        assert_eq!((33, metrics(3, 3, 4)), run(s, Fit::Worst, true));
+    }
+
+    #[test]
+    fn free_list_coalesces() {
+        let mut free = FreeList::new(vec![0, 5, 10]);
+        free.release(10, 2);
+        free.release(2, 3);
+        assert_eq!(Some(2), free.find(Fit::First, 2, 100));
+        assert_eq!(Some(10), free.find(Fit::Best, 2, 100));
+        assert_eq!(None, free.find(Fit::First, 4, 100));
+
+        free.release(5, 5);
+        assert_eq!(Some(2), free.find(Fit::First, 4, 100));
+        assert_eq!(vec![(2, 10)], free.by_start.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn free_list_slots() {
+        // One slot per disk map entry, not per block
+        let disk = Disk::new(&parse(EXAMPLE));
+        let entries = parse(EXAMPLE).iter().filter(|c| c.len() != 0).count();
+        assert_eq!(entries, disk.free.slots.len());
+        assert_eq!(
+            disk.free.slots.len().next_power_of_two(),
+            disk.free.longest.leaves
+        );
+
+        // Several extents may start in the same slot
+        let mut free = FreeList::new(vec![0, 20]);
+        free.release(1, 1);
+        free.release(4, 3);
+        free.release(10, 2);
+        assert_eq!(Some(4), free.find(Fit::First, 2, 100));
+        assert_eq!(Some(10), free.find(Fit::Best, 2, 100));
+        assert_eq!(Some(4), free.find(Fit::Worst, 1, 100));
+        assert_eq!(Some(10), free.find(Fit::Next { cursor: 5 }, 2, 100));
+        assert_eq!(Some(4), free.find(Fit::Next { cursor: 11 }, 2, 100));
+
+        // Extents must start before the limit, even mid-slot
+        assert_eq!(None, free.find(Fit::First, 3, 4));
+        assert_eq!(Some(1), free.find(Fit::First, 1, 4));
+        assert_eq!(3, free.largest());
+
+        free.allocate(4, 3);
+        assert_eq!(2, free.largest());
+        assert_eq!(None, free.find(Fit::First, 3, 100));
+    }
+
+    #[test]
+    fn max_tree_queries() {
+        let mut tree = MaxTree::new(10);
+        tree.set(1, 3);
+        tree.set(4, 5);
+        tree.set(8, 2);
+
+        assert_eq!(5, tree.max(0..10));
+        assert_eq!(3, tree.max(0..4));
+        assert_eq!(2, tree.max(5..100));
+
+        assert_eq!(Some(1), tree.leftmost(0..10, 2));
+        assert_eq!(Some(4), tree.leftmost(0..10, 4));
+        assert_eq!(Some(8), tree.leftmost(5..10, 1));
+        assert_eq!(None, tree.leftmost(0..4, 4));
+        assert_eq!(None, tree.leftmost(5..8, 1));
+
+        tree.set(4, 0);
+        assert_eq!(None, tree.leftmost(0..10, 4));
+        assert_eq!(3, tree.max(0..10));
+    }
 }
@@ -1,4 +1,10 @@
-use std::collections::{BTreeMap, BTreeSet};
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
 
 const INPUT: &str = include_str!("../input.txt");
 
@@ -9,23 +15,32 @@ fn main() {
 
 fn trailhead_score_sum(s: &str) -> usize {
     let (map, bounds) = parse(s);
+    score_sum(&map, &bounds, &Rules::STANDARD)
+}
 
-    let mut paths = BTreeMap::new();
+fn trailhead_rating_sum(s: &str) -> usize {
+    let (map, bounds) = parse(s);
+    rating_sum(&map, &bounds, &Rules::STANDARD)
+}
 
-    let coords_for_level = |l| coords_for_level(&map, l);
+fn score_sum(map: &Map, bounds: &Bounds, rules: &Rules) -> usize {
+    let levels = levels(map);
 
-    // Mark all level 9 coordindates as reachable by themselves
-    for &c in coords_for_level(9) {
-        paths.entry(c).or_insert_with(BTreeSet::new).insert(c);
+    let mut paths = BTreeMap::new();
+
+    // Mark all ending coordinates as reachable by themselves
+    for &c in levels.get(&rules.end).into_iter().flatten() {
+        paths.insert(c, BTreeSet::from([c]));
     }
 
-    // For each previous level, see if we can reach the next-higher level
-    for level in (0..9).rev() {
-        for &c in coords_for_level(level) {
+    // For each lower level, see which squares we can climb to. Those
+    // squares are higher, so we've already been there.
+    for (&level, coords) in levels.range(rules.start..rules.end).rev() {
+        for &c in coords {
             let mut found = BTreeSet::new();
 
             for n in bounds.neighbors(c) {
-                if map.get(&n).copied() == Some(level + 1) {
+                if map.get(&n).is_some_and(|&l| rules.can_climb(level, l)) {
                     if let Some(p) = paths.get(&n) {
                         found.extend(p.iter().copied());
                     }
@@ -36,58 +51,237 @@ fn trailhead_score_sum(s: &str) -> usize {
         }
     }
 
-    // Find how many unique level 9 squares we reached from each level 0
-    coords_for_level(0)
+    // Find how many unique ending squares we reached from each start
+    levels
+        .get(&rules.start)
+        .into_iter()
+        .flatten()
         .map(|c| paths.get(c).map_or(0, |f| f.len()))
         .sum()
 }
 
-fn trailhead_rating_sum(s: &str) -> usize {
-    let (map, bounds) = parse(s);
-
-    let coords_for_level = |l| coords_for_level(&map, l);
+fn rating_sum(map: &Map, bounds: &Bounds, rules: &Rules) -> usize {
+    let levels = levels(map);
 
     let mut ratings = BTreeMap::new();
 
-    // Mark all level 9 coordinates as having one path to itself
-    ratings.extend(coords_for_level(9).map(|&c| (c, 1)));
-
-    // Find coordindates for each previous level
-    for level in (1..=9).rev() {
-        for &c in coords_for_level(level) {
-            // If we've visited this square
-            if let Some(&paths_to_here) = ratings.get(&c) {
-                // Check to see if the neighbors are one level away
-                for n in bounds.neighbors(c) {
-                    if map[&n] == level - 1 {
-                        // And mark them as having a number of possible paths
-                        *ratings.entry(n).or_insert(0) += paths_to_here;
-                    }
-                }
-            }
+    // Mark all ending coordinates as having one path to itself
+    ratings.extend(
+        levels
+            .get(&rules.end)
+            .into_iter()
+            .flatten()
+            .map(|&c| (c, 1)),
+    );
+
+    // Each lower square has as many paths as the squares it can
+    // climb to, combined.
+    for (&level, coords) in levels.range(rules.start..rules.end).rev() {
+        for &c in coords {
+            let paths_from_here = bounds
+                .neighbors(c)
+                .filter(|n| map.get(n).is_some_and(|&l| rules.can_climb(level, l)))
+                .map(|n| ratings.get(&n).copied().unwrap_or(0))
+                .sum();
+
+            ratings.insert(c, paths_from_here);
         }
     }
 
     // Add up all possible paths
-    coords_for_level(0)
+    levels
+        .get(&rules.start)
+        .into_iter()
+        .flatten()
         .map(|c| ratings.get(c).copied().unwrap_or(0))
         .sum()
 }
 
+#[derive(Debug, Clone, PartialEq)]
+struct Rules {
+    start: u32,
+    end: u32,
+    /// How much higher each step must be
+    climb: RangeInclusive<u32>,
+}
+
+impl Rules {
+    pub const STANDARD: Self = Self {
+        start: 0,
+        end: 9,
+        climb: 1..=1,
+    };
+
+    pub fn new(start: u32, end: u32, climb: RangeInclusive<u32>) -> Self {
+        assert!(start <= end, "Trails must end above where they start");
+        // Otherwise a trail could walk in circles forever
+        assert!(*climb.start() > 0, "Every step must climb");
+
+        Self { start, end, climb }
+    }
+
+    fn can_climb(&self, from: u32, to: u32) -> bool {
+        to <= self.end
+            && to
+                .checked_sub(from)
+                .is_some_and(|d| self.climb.contains(&d))
+    }
+}
+
+/// Every distinct trail from a trailhead, depth first. Trails are
+/// only found as they are requested, so callers may stop early.
+struct Trails<'a> {
+    map: &'a Map,
+    bounds: &'a Bounds,
+    rules: &'a Rules,
+    trailhead: Coord,
+    path: Vec<Coord>,
+    /// The squares still to explore after each step of the path
+    pending: Vec<Vec<Coord>>,
+}
+
+impl<'a> Trails<'a> {
+    pub fn new(map: &'a Map, bounds: &'a Bounds, rules: &'a Rules, trailhead: Coord) -> Self {
+        Self {
+            map,
+            bounds,
+            rules,
+            trailhead,
+            path: Vec::new(),
+            pending: vec![vec![trailhead]],
+        }
+    }
+
+    /// Only counts the trails that haven't already been returned by
+    /// [`Iterator::next`].
+    pub fn summary(self) -> TrailSummary {
+        let trailhead = self.trailhead;
+
+        let mut peaks = BTreeSet::new();
+        let mut rating = 0;
+
+        for trail in self {
+            peaks.extend(trail.last().copied());
+            rating += 1;
+        }
+
+        TrailSummary {
+            trailhead,
+            score: peaks.len(),
+            rating,
+        }
+    }
+}
+
+impl Iterator for Trails<'_> {
+    type Item = Vec<Coord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(c) = self.pending.last_mut()?.pop() else {
+                // Nowhere left to go from here, back up a step
+                self.pending.pop();
+                self.path.pop();
+                continue;
+            };
+
+            self.path.push(c);
+
+            let level = self.map[&c];
+            if level == self.rules.end {
+                let trail = self.path.clone();
+                self.path.pop();
+                return Some(trail);
+            }
+
+            let mut next = self
+                .bounds
+                .neighbors(c)
+                .filter(|n| {
+                    let l = self.map.get(n);
+                    l.is_some_and(|&l| self.rules.can_climb(level, l))
+                })
+                .collect::<Vec<_>>();
+            // Popping from the end, so reverse to keep neighbor order
+            next.reverse();
+            self.pending.push(next);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct TrailSummary {
+    pub trailhead: Coord,
+    /// The number of distinct ending squares reached
+    pub score: usize,
+    /// The number of distinct trails
+    pub rating: usize,
+}
+
+/// The trails for each trailhead, in coordinate order.
+fn trails<'a>(
+    map: &'a Map,
+    bounds: &'a Bounds,
+    rules: &'a Rules,
+) -> impl Iterator<Item = Trails<'a>> {
+    let trailheads = map.iter().filter(|&(_, &l)| l == rules.start);
+    trailheads.map(move |(&c, _)| Trails::new(map, bounds, rules, c))
+}
+
+/// Shows the level of each square that is part of a trail and `.`
+/// for everything else.
+fn render_trails<'a>(
+    map: &Map,
+    bounds: &Bounds,
+    encoding: Encoding,
+    trails: impl IntoIterator<Item = &'a [Coord]>,
+) -> String {
+    let on_trail = trails.into_iter().flatten().collect::<BTreeSet<_>>();
+
+    let width = map
+        .values()
+        .map(|&l| encoding.format(l).len())
+        .max()
+        .unwrap_or(1);
+
+    let mut out = String::new();
+
+    for y in 0..=bounds.1 {
+        let row = (0..=bounds.0).map(|x| {
+            let c = (x, y);
+
+            let square = if on_trail.contains(&c) {
+                encoding.format(map[&c])
+            } else {
+                ".".into()
+            };
+            format!("{square:>width$}")
+        });
+
+        let row = row.collect::<Vec<_>>();
+        out.push_str(&row.join(encoding.separator()));
+        out.push('\n');
+    }
+
+    out
+}
+
 type Coord = (usize, usize);
 
 type Map = BTreeMap<Coord, u32>;
 
 fn parse(s: &str) -> (Map, Bounds) {
+    parse_with(s, Encoding::Digits)
+}
+
+fn parse_with(s: &str, encoding: Encoding) -> (Map, Bounds) {
     let mut map = BTreeMap::new();
     let mut max_x = 0;
     let mut max_y = 0;
 
     for (y, l) in s.lines().enumerate() {
-        for (x, c) in l.chars().enumerate() {
-            let c = c.to_digit(10).expect("Invalid digit");
-
-            map.insert((x, y), c);
+        for (x, level) in encoding.levels(l).enumerate() {
+            map.insert((x, y), level);
 
             max_x = x;
         }
@@ -99,10 +293,60 @@ fn parse(s: &str) -> (Map, Bounds) {
     (map, bounds)
 }
 
-fn coords_for_level(map: &Map, level: u32) -> impl Iterator<Item = &Coord> {
-    map.iter()
-        .filter(move |&(_c, &l)| l == level)
-        .map(|(c, _l)| c)
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Encoding {
+    /// `0` through `9`
+    Digits,
+    /// `a` through `z`, as 0 through 25
+    Letters,
+    /// Whitespace-separated numbers of any size
+    Numbers,
+}
+
+impl Encoding {
+    fn levels(self, l: &str) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            Encoding::Digits => Box::new(l.chars().map(|c| c.to_digit(10).expect("Invalid digit"))),
+
+            Encoding::Letters => Box::new(l.chars().map(|c| {
+                assert!(c.is_ascii_lowercase(), "Invalid letter");
+                u32::from(c) - u32::from('a')
+            })),
+
+            Encoding::Numbers => Box::new(
+                l.split_whitespace()
+                    .map(|n| n.parse().expect("Invalid number")),
+            ),
+        }
+    }
+
+    fn format(self, level: u32) -> String {
+        match self {
+            Encoding::Digits => char::from_digit(level, 10).expect("Invalid level").into(),
+            Encoding::Letters => char::from_u32(u32::from('a') + level)
+                .expect("Invalid level")
+                .into(),
+            Encoding::Numbers => level.to_string(),
+        }
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            Encoding::Digits | Encoding::Letters => "",
+            Encoding::Numbers => " ",
+        }
+    }
+}
+
+/// All the squares at each level.
+fn levels(map: &Map) -> BTreeMap<u32, Vec<Coord>> {
+    let mut levels = BTreeMap::<_, Vec<_>>::new();
+
+    for (&c, &l) in map {
+        levels.entry(l).or_default().push(c);
+    }
+
+    levels
 }
 
 #[derive(Debug)]
@@ -148,4 +392,122 @@ mod test {
     fn example_ratings() {
         assert_eq!(81, trailhead_rating_sum(EXAMPLE_2));
     }
+
+    #[test]
+    fn example_trails() {
+        let (map, bounds) = parse(EXAMPLE_1);
+
+        let mut heads = trails(&map, &bounds, &Rules::STANDARD);
+        let mut head = heads.next().expect("Missing trailhead");
+        assert!(heads.next().is_none());
+
+        let first = head.next().expect("Missing trail");
+        assert_eq!(10, first.len());
+        assert_eq!(
+            render_trails(&map, &bounds, Encoding::Digits, [&first[..]]),
+            "\
+0123
+...4
+...5
+9876
+",
+        );
+
+        // We've already seen one trail
+        assert_eq!(15, head.count());
+
+        let (map, bounds) = parse(EXAMPLE_2);
+        let summaries = trails(&map, &bounds, &Rules::STANDARD)
+            .map(Trails::summary)
+            .collect::<Vec<_>>();
+
+        assert_eq!(9, summaries.len());
+        assert_eq!(
+            TrailSummary {
+                trailhead: (0, 6),
+                score: 5,
+                rating: 5,
+            },
+            summaries[0],
+        );
+        assert_eq!(36, summaries.iter().map(|s| s.score).sum::<usize>());
+        assert_eq!(81, summaries.iter().map(|s| s.rating).sum::<usize>());
+
+        // Trails already taken aren't part of the summary
+        let mut head = trails(&map, &bounds, &Rules::STANDARD)
+            .next()
+            .expect("Missing trailhead");
+        head.next().expect("Missing trail");
+        let expected = TrailSummary {
+            trailhead: (0, 6),
+            score: 4,
+            rating: 4,
+        };
+        assert_eq!(expected, head.summary());
+    }
+
+    #[test]
+    fn custom_rules() {
+        let (map, bounds) = parse("02\n13");
+
+        assert_eq!(0, rating_sum(&map, &bounds, &Rules::new(0, 3, 1..=1)));
+
+        let rules = Rules::new(0, 3, 1..=2);
+        assert_eq!(1, score_sum(&map, &bounds, &rules));
+        assert_eq!(2, rating_sum(&map, &bounds, &rules));
+
+        let mut heads = trails(&map, &bounds, &rules);
+        let head = heads.next().expect("Missing trailhead");
+        let all = head.collect::<Vec<_>>();
+        assert_eq!(
+            vec![vec![(0, 0), (1, 0), (1, 1)], vec![(0, 0), (0, 1), (1, 1)]],
+            all
+        );
+    }
+
+    #[test]
+    fn example_encodings() {
+        let letters = EXAMPLE_2
+            .chars()
+            .map(|c| match c.to_digit(10) {
+                Some(d) => char::from_u32(u32::from('a') + d).unwrap(),
+                None => c,
+            })
+            .collect::<String>();
+        let (map, bounds) = parse_with(&letters, Encoding::Letters);
+        assert_eq!(36, score_sum(&map, &bounds, &Rules::STANDARD));
+
+        let numbers = EXAMPLE_2
+            .lines()
+            .map(|l| {
+                let squares = l
+                    .chars()
+                    .map(|c| (c.to_digit(10).unwrap() * 10).to_string());
+                squares.collect::<Vec<_>>().join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (map, bounds) = parse_with(&numbers, Encoding::Numbers);
+        let rules = Rules::new(0, 90, 10..=10);
+        assert_eq!(36, score_sum(&map, &bounds, &rules));
+        assert_eq!(81, rating_sum(&map, &bounds, &rules));
+
+        let first = trails(&map, &bounds, &rules)
+            .next()
+            .and_then(|mut t| t.next())
+            .expect("Missing trail");
+        let rendered = render_trails(&map, &bounds, Encoding::Numbers, [&first[..]]);
+        // Leading spaces keep the columns aligned
+        let expected = [
+            " . 90  .  .  .  .  .  .",
+            " . 80  .  .  .  .  .  .",
+            " . 70  .  .  .  .  .  .",
+            " . 60  .  .  .  .  .  .",
+            "40 50  .  .  .  .  .  .",
+            "30 20  .  .  .  .  .  .",
+            " 0 10  .  .  .  .  .  .",
+            " .  .  .  .  .  .  .  .",
+        ];
+        assert_eq!(rendered.lines().collect::<Vec<_>>(), expected);
+    }
 }
@@ -1,5 +1,8 @@
-use itertools::Either;
-use std::collections::BTreeMap;
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
+use num_bigint::BigUint;
+use std::collections::{BTreeMap, BTreeSet};
 
 const INPUT: &str = include_str!("../input.txt");
 
@@ -10,32 +13,16 @@ fn main() {
 }
 
 fn stones_after_blinks(s: &str, n_blinks: usize) -> usize {
-    let mut stones: Vec<u64> = s
-        .split_ascii_whitespace()
-        .map(|n| n.parse().expect("Invalid number"))
-        .collect();
+    stones_after_blinks_with(s, n_blinks, STANDARD_RULES)
+}
+
+fn stones_after_blinks_with(s: &str, n_blinks: usize, rules: &[Rule]) -> usize {
+    let mut stones: Vec<u64> = parse_stones(s).collect();
 
     for _ in 0..n_blinks {
         stones = stones
             .into_iter()
-            .flat_map(|stone| {
-                if stone == 0 {
-                    return Either::Left([1].into_iter());
-                }
-
-                let n_digits = stone.ilog10() + 1;
-
-                if n_digits % 2 == 0 {
-                    let factor = 10u64.pow(n_digits / 2);
-
-                    let l = stone / factor;
-                    let r = stone % factor;
-
-                    return Either::Right([l, r].into_iter());
-                }
-
-                Either::Left([stone * 2024].into_iter())
-            })
+            .flat_map(|stone| blink(rules, &stone).expect("Stone value overflowed"))
             .collect();
     }
 
@@ -88,6 +75,365 @@ fn delve(memo: &mut BTreeMap<(u64, usize), usize>, stone: u64, depth: usize) ->
     count
 }
 
+/// Stone values and counts are both limited to a `u64`. Instead of
+/// wrapping or panicking, reports where they would overflow.
+fn stones_after_blinks_checked(s: &str, n_blinks: usize, rules: &[Rule]) -> Result<u64, Overflow> {
+    count_stones::<u64, u64>(s, n_blinks, rules)
+}
+
+/// Stone values and counts are both unbounded.
+fn stones_after_blinks_big(s: &str, n_blinks: usize, rules: &[Rule]) -> BigUint {
+    count_stones::<BigUint, BigUint>(s, n_blinks, rules).expect("Big integers cannot overflow")
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Overflow {
+    /// A rule produced a stone value that doesn't fit
+    Value { blink: usize, stone: u64 },
+    /// There are too many stones to count
+    Count { blink: usize },
+}
+
+fn count_stones<S: Stone, C: Count>(
+    s: &str,
+    n_blinks: usize,
+    rules: &[Rule],
+) -> Result<C, Overflow> {
+    let stones = histogram::<S, C>(s, n_blinks, rules)?;
+    Ok(total_count(stones.values()).expect("Total was already checked"))
+}
+
+/// How many stones carry each distinct value after blinking.
+fn stone_histogram(
+    s: &str,
+    n_blinks: usize,
+    rules: &[Rule],
+) -> Result<BTreeMap<u64, u64>, Overflow> {
+    histogram(s, n_blinks, rules)
+}
+
+/// Tracks how many stones have each value instead of recursing, so
+/// thousands of blinks don't exhaust the stack.
+fn histogram<S: Stone, C: Count>(
+    s: &str,
+    n_blinks: usize,
+    rules: &[Rule],
+) -> Result<BTreeMap<S, C>, Overflow> {
+    let mut stones = BTreeMap::<S, C>::new();
+
+    for stone in parse_stones(s) {
+        let count = stones.entry(S::from_u64(stone)).or_insert_with(C::zero);
+        *count = count.add(&C::one()).ok_or(Overflow::Count { blink: 0 })?;
+    }
+
+    for blink_idx in 1..=n_blinks {
+        let mut next = BTreeMap::<S, C>::new();
+
+        for (stone, count) in stones {
+            let new_stones = blink(rules, &stone).ok_or_else(|| Overflow::Value {
+                blink: blink_idx,
+                stone: stone.saturating_u64(),
+            })?;
+
+            for new_stone in new_stones {
+                let total = next.entry(new_stone).or_insert_with(C::zero);
+                *total = total
+                    .add(&count)
+                    .ok_or(Overflow::Count { blink: blink_idx })?;
+            }
+        }
+
+        stones = next;
+
+        // Even if each value's count fits, the total may not
+        total_count(stones.values()).ok_or(Overflow::Count { blink: blink_idx })?;
+    }
+
+    Ok(stones)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Cycle {
+    /// The first blink whose set of values repeats later
+    pub start: usize,
+    pub period: usize,
+}
+
+/// Looks at which values appear, ignoring how many stones carry
+/// them. Because the next set only depends on the current one, the
+/// first repeated set starts a cycle. Gives up after `max_blinks`.
+fn find_value_cycle(s: &str, max_blinks: usize, rules: &[Rule]) -> Result<Option<Cycle>, Overflow> {
+    let mut values = parse_stones(s).collect::<BTreeSet<_>>();
+    let mut seen = BTreeMap::new();
+
+    for blink_idx in 0..=max_blinks {
+        if let Some(start) = seen.insert(values.clone(), blink_idx) {
+            let period = blink_idx - start;
+            return Ok(Some(Cycle { start, period }));
+        }
+
+        let mut next = BTreeSet::new();
+        for stone in values {
+            let new_stones = blink(rules, &stone).ok_or(Overflow::Value {
+                blink: blink_idx + 1,
+                stone,
+            })?;
+            next.extend(new_stones);
+        }
+        values = next;
+    }
+
+    Ok(None)
+}
+
+fn parse_stones(s: &str) -> impl Iterator<Item = u64> + '_ {
+    s.split_ascii_whitespace()
+        .map(|n| n.parse().expect("Invalid number"))
+}
+
+fn total_count<'a, C: Count + 'a>(counts: impl IntoIterator<Item = &'a C>) -> Option<C> {
+    counts
+        .into_iter()
+        .try_fold(C::zero(), |total, count| total.add(count))
+}
+
+const STANDARD_RULES: &[Rule] = &[
+    Rule {
+        when: Condition::Value(0),
+        then: Transform::Replace(1),
+    },
+    Rule {
+        when: Condition::DigitsMultipleOf(2),
+        then: Transform::SplitDigits(2),
+    },
+    Rule {
+        when: Condition::Always,
+        then: Transform::Multiply(2024),
+    },
+];
+
+/// Applies the first matching rule. A stone that matches no rule is
+/// left unchanged. Returns `None` if a new stone is too large or the
+/// rule splits into zero parts.
+fn blink<S: Stone>(rules: &[Rule], stone: &S) -> Option<Vec<S>> {
+    match rules.iter().find(|r| r.when.matches(stone)) {
+        Some(rule) => rule.then.apply(stone),
+        None => Some(vec![stone.clone()]),
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Rule {
+    pub when: Condition,
+    pub then: Transform,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Condition {
+    Value(u64),
+    /// Never matches a multiple of zero
+    ValueMultipleOf(u64),
+    /// Never matches a multiple of zero
+    DigitsMultipleOf(u32),
+    Always,
+}
+
+impl Condition {
+    fn matches(self, stone: &impl Stone) -> bool {
+        match self {
+            Condition::Value(v) => stone.is(v),
+            Condition::ValueMultipleOf(0) | Condition::DigitsMultipleOf(0) => false,
+            Condition::ValueMultipleOf(m) => stone.rem(m) == 0,
+            Condition::DigitsMultipleOf(m) => stone.n_digits() % m == 0,
+            Condition::Always => true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Transform {
+    Replace(u64),
+    /// Splits the digits into this many equal parts. If they don't
+    /// divide evenly, the leftmost stone gets the extra digits; with
+    /// fewer digits than parts, the rest are zero. Can't split into
+    /// zero parts.
+    SplitDigits(u32),
+    Multiply(u64),
+    Add(u64),
+}
+
+impl Transform {
+    fn apply<S: Stone>(self, stone: &S) -> Option<Vec<S>> {
+        match self {
+            Transform::Replace(v) => Some(vec![S::from_u64(v)]),
+            Transform::SplitDigits(0) => None,
+            Transform::SplitDigits(parts) => Some(stone.split_digits(parts)),
+            Transform::Multiply(m) => Some(vec![stone.times(m)?]),
+            Transform::Add(a) => Some(vec![stone.plus(a)?]),
+        }
+    }
+}
+
+/// The value engraved on a stone.
+trait Stone: Ord + Clone {
+    fn from_u64(v: u64) -> Self;
+
+    /// For reporting; values too large become `u64::MAX`.
+    fn saturating_u64(&self) -> u64;
+
+    fn is(&self, v: u64) -> bool;
+
+    fn rem(&self, m: u64) -> u64;
+
+    fn n_digits(&self) -> u32;
+
+    fn split_digits(&self, parts: u32) -> Vec<Self>;
+
+    /// `None` on overflow.
+    fn times(&self, m: u64) -> Option<Self>;
+
+    /// `None` on overflow.
+    fn plus(&self, a: u64) -> Option<Self>;
+}
+
+impl Stone for u64 {
+    fn from_u64(v: u64) -> Self {
+        v
+    }
+
+    fn saturating_u64(&self) -> u64 {
+        *self
+    }
+
+    fn is(&self, v: u64) -> bool {
+        *self == v
+    }
+
+    fn rem(&self, m: u64) -> u64 {
+        self % m
+    }
+
+    fn n_digits(&self) -> u32 {
+        self.checked_ilog10().unwrap_or(0) + 1
+    }
+
+    fn split_digits(&self, parts: u32) -> Vec<Self> {
+        let factor = 10u64.pow(self.n_digits() / parts);
+
+        let mut stone = *self;
+        let mut stones = Vec::new();
+        for _ in 1..parts {
+            stones.push(stone % factor);
+            stone /= factor;
+        }
+        stones.push(stone);
+
+        stones.reverse();
+        stones
+    }
+
+    fn times(&self, m: u64) -> Option<Self> {
+        self.checked_mul(m)
+    }
+
+    fn plus(&self, a: u64) -> Option<Self> {
+        self.checked_add(a)
+    }
+}
+
+/// How many stones there are.
+trait Count: Clone {
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    /// `None` on overflow.
+    fn add(&self, other: &Self) -> Option<Self>;
+}
+
+impl Count for u64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        self.checked_add(*other)
+    }
+}
+
+impl Stone for BigUint {
+    fn from_u64(v: u64) -> Self {
+        v.into()
+    }
+
+    fn saturating_u64(&self) -> u64 {
+        self.try_into().unwrap_or(u64::MAX)
+    }
+
+    fn is(&self, v: u64) -> bool {
+        *self == v.into()
+    }
+
+    fn rem(&self, m: u64) -> u64 {
+        (self % m)
+            .try_into()
+            .expect("Remainder is smaller than the divisor")
+    }
+
+    fn n_digits(&self) -> u32 {
+        let digits = self.to_string().len();
+        u32::try_from(digits).expect("Too many digits")
+    }
+
+    fn split_digits(&self, parts: u32) -> Vec<Self> {
+        let digits = self.to_string();
+        let part_len = digits.len() / usize::try_from(parts).expect("Too many parts");
+        // With fewer digits than parts, the right-hand stones are empty
+        let parse = |s: &str| match s {
+            "" => Self::ZERO,
+            s => s.parse().expect("Invalid number"),
+        };
+
+        let mut stones = Vec::new();
+        let mut rest = &digits[..];
+        for _ in 1..parts {
+            let (head, tail) = rest.split_at(rest.len() - part_len);
+            stones.push(parse(tail));
+            rest = head;
+        }
+        stones.push(parse(rest));
+
+        stones.reverse();
+        stones
+    }
+
+    fn times(&self, m: u64) -> Option<Self> {
+        Some(self * m)
+    }
+
+    fn plus(&self, a: u64) -> Option<Self> {
+        Some(self + a)
+    }
+}
+
+impl Count for BigUint {
+    fn zero() -> Self {
+        Self::ZERO
+    }
+
+    fn one() -> Self {
+        1u8.into()
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -116,4 +462,235 @@ mod test {
         assert_eq!(22, stones_after_blinks_memo(EXAMPLE_2, 6));
         assert_eq!(55312, stones_after_blinks_memo(EXAMPLE_2, 25));
     }
+
+    #[test]
+    fn example_2_checked() {
+        let checked = |n| stones_after_blinks_checked(EXAMPLE_2, n, STANDARD_RULES);
+        assert_eq!(Ok(55312), checked(25));
+        assert_eq!(
+            Ok(stones_after_blinks_memo(EXAMPLE_2, 75) as u64),
+            checked(75)
+        );
+        assert_eq!(Err(Overflow::Count { blink: 106 }), checked(110));
+    }
+
+    #[test]
+    fn custom_rules() {
+        let rules = &[
+            Rule {
+                when: Condition::Value(0),
+                then: Transform::Replace(1),
+            },
+            Rule {
+                when: Condition::DigitsMultipleOf(3),
+                then: Transform::SplitDigits(3),
+            },
+            Rule {
+                when: Condition::Always,
+                then: Transform::Multiply(2),
+            },
+        ];
+
+        // 5 -> 10 -> 20 -> 40 -> 80 -> 160 -> 1 6 0
+        assert_eq!(Some(vec![1, 6, 0]), blink(rules, &160u64));
+        assert_eq!(3, stones_after_blinks_with("5", 6, rules));
+        assert_eq!(Ok(3), stones_after_blinks_checked("5", 6, rules));
+
+        let naive = stones_after_blinks_with(EXAMPLE_2, 20, rules);
+        assert_eq!(
+            Ok(naive as u64),
+            stones_after_blinks_checked(EXAMPLE_2, 20, rules)
+        );
+
+        let rules = &[
+            Rule {
+                when: Condition::ValueMultipleOf(11),
+                then: Transform::SplitDigits(2),
+            },
+            Rule {
+                when: Condition::Always,
+                then: Transform::Add(4),
+            },
+        ];
+
+        // 3 -> 7 -> 11 -> 1 1 -> 5 5 -> 9 9
+        assert_eq!(Some(vec![1, 1]), blink(rules, &11u64));
+        assert_eq!(Some(vec![7]), blink(rules, &3u64));
+        assert_eq!(2, stones_after_blinks_with("3", 5, rules));
+        assert_eq!(Ok(2), stones_after_blinks_checked("3", 5, rules));
+    }
+
+    #[test]
+    fn degenerate_rules() {
+        let rule = |when, then| {
+            [Rule { when, then }, Rule {
+                when: Condition::Always,
+                then: Transform::Add(1),
+            }]
+        };
+
+        // Nothing is a multiple of zero
+        let rules = rule(Condition::ValueMultipleOf(0), Transform::Replace(0));
+        assert_eq!(Some(vec![1]), blink(&rules, &0u64));
+        assert_eq!(
+            Some(vec![BigUint::from(11u8)]),
+            blink(&rules, &BigUint::from(10u8))
+        );
+
+        let rules = rule(Condition::DigitsMultipleOf(0), Transform::Replace(0));
+        assert_eq!(Some(vec![11]), blink(&rules, &10u64));
+
+        // Splitting into zero parts has no answer
+        let rules = rule(Condition::Always, Transform::SplitDigits(0));
+        assert_eq!(None, blink(&rules, &1234u64));
+        assert_eq!(None, blink(&rules, &BigUint::from(1234u16)));
+        assert_eq!(
+            Err(Overflow::Value {
+                blink: 1,
+                stone: 12
+            }),
+            stones_after_blinks_checked("12", 1, &rules),
+        );
+
+        // Uneven splits favor the leftmost stone, then pad with zeros
+        let rules = rule(Condition::Always, Transform::SplitDigits(3));
+        assert_eq!(Some(vec![123, 45, 67]), blink(&rules, &1234567u64));
+        assert_eq!(Some(vec![5, 0, 0]), blink(&rules, &5u64));
+    }
+
+    #[test]
+    fn big_blinks() {
+        assert_eq!(
+            Ok(55312),
+            stones_after_blinks_checked(EXAMPLE_2, 25, STANDARD_RULES),
+        );
+        assert_eq!(
+            "55312",
+            stones_after_blinks_big(EXAMPLE_2, 25, STANDARD_RULES).to_string(),
+        );
+
+        // Roughly 1.5x more stones each blink
+        assert_eq!(
+            Err(Overflow::Count { blink: 106 }),
+            stones_after_blinks_checked(EXAMPLE_2, 200, STANDARD_RULES),
+        );
+        let fits = |n| {
+            let big = stones_after_blinks_big(EXAMPLE_2, n, STANDARD_RULES);
+            big.to_string().parse::<u64>().is_ok()
+        };
+        assert!(fits(105));
+        assert!(!fits(106));
+
+        let big = stones_after_blinks_big(EXAMPLE_2, 2000, STANDARD_RULES);
+        assert_eq!(364, big.to_string().len());
+
+        let checked = stones_after_blinks_checked(EXAMPLE_2, 75, STANDARD_RULES);
+        let big = stones_after_blinks_big(EXAMPLE_2, 75, STANDARD_RULES);
+        assert_eq!(checked.map(|c| c.to_string()), Ok(big.to_string()));
+
+        // Without splitting, values grow without bound
+        let rules = &[Rule {
+            when: Condition::Always,
+            then: Transform::Multiply(2024),
+        }];
+        assert_eq!(
+            Err(Overflow::Value {
+                blink: 6,
+                stone: 17 * 2024u64.pow(5),
+            }),
+            stones_after_blinks_checked(EXAMPLE_2, 10, rules),
+        );
+        assert_eq!(
+            "2",
+            stones_after_blinks_big(EXAMPLE_2, 10, rules).to_string()
+        );
+    }
+
+    #[test]
+    fn histogram() {
+        // 253000 1 7 -> 253 0 2024 14168
+        let histogram = stone_histogram(EXAMPLE_2, 2, STANDARD_RULES).unwrap();
+        assert_eq!(
+            BTreeMap::from([(0, 1), (253, 1), (2024, 1), (14168, 1)]),
+            histogram
+        );
+
+        let histogram = stone_histogram(EXAMPLE_2, 25, STANDARD_RULES).unwrap();
+        assert_eq!(55312, histogram.values().sum::<u64>());
+        assert!(histogram.values().all(|&c| c > 0));
+    }
+
+    #[test]
+    fn value_cycle() {
+        let rules = &[
+            Rule {
+                when: Condition::Value(0),
+                then: Transform::Replace(1),
+            },
+            Rule {
+                when: Condition::Value(1),
+                then: Transform::Replace(2),
+            },
+            Rule {
+                when: Condition::Value(2),
+                then: Transform::Replace(0),
+            },
+        ];
+        assert_eq!(
+            Ok(Some(Cycle {
+                start: 0,
+                period: 3
+            })),
+            find_value_cycle("0", 10, rules),
+        );
+        // {0, 1} -> {1, 2} -> {2, 0} -> {0, 1}
+        assert_eq!(
+            Ok(Some(Cycle {
+                start: 0,
+                period: 3
+            })),
+            find_value_cycle("0 1", 10, rules),
+        );
+        assert_eq!(Ok(None), find_value_cycle("0", 2, rules));
+
+        // The standard rules settle on a fixed set of values
+        assert_eq!(
+            Ok(Some(Cycle {
+                start: 17,
+                period: 1
+            })),
+            find_value_cycle(EXAMPLE_2, 100, STANDARD_RULES),
+        );
+    }
+
+    #[test]
+    fn split_digits() {
+        for n in [7, 10, 1000, 12345, 123456, 1_000_000] {
+            for parts in 1..=8 {
+                let small = n.split_digits(parts);
+                let big = BigUint::from_u64(n).split_digits(parts);
+                let big = big.iter().map(BigUint::saturating_u64).collect::<Vec<_>>();
+                assert_eq!(small, big, "{n} in {parts} parts");
+            }
+        }
+
+        assert_eq!(vec![7, 0], 7u64.split_digits(2));
+        assert_eq!(vec![12, 3, 4], 1234u64.split_digits(3));
+    }
+
+    #[test]
+    fn big_uint() {
+        let n = "123456789012345678901234567890".parse::<BigUint>().unwrap();
+        assert_eq!(30, n.n_digits());
+        let parts = n
+            .split_digits(2)
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(["123456789012345", "678901234567890"], &parts[..]);
+        assert_eq!(u64::MAX, n.saturating_u64());
+        assert_eq!(1234567890 % 7, BigUint::from_u64(1234567890).rem(7));
+        assert!(BigUint::from_u64(0).is(0));
+        assert_eq!(1, BigUint::from_u64(0).n_digits());
+    }
 }
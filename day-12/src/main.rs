@@ -1,6 +1,10 @@
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
 use bitflags::bitflags;
 use itertools::Itertools;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
 
 const INPUT: &str = include_str!("../input.txt");
 
@@ -66,18 +70,19 @@ fn find_regions(map: &Map) -> Vec<Region> {
 
 #[derive(Debug)]
 struct Region {
-    #[allow(dead_code)]
     label: char,
     tiles: BTreeSet<Coord>,
     perimeter: BTreeMap<Coord, Direction>,
+    /// A polygon has as many sides as corners
+    corners: usize,
 }
 
 impl Region {
-    fn price(&self) -> usize {
+    pub fn price(&self) -> usize {
         self.n_perimeters() * self.tiles.len()
     }
 
-    fn price_bulk(&self) -> usize {
+    pub fn price_bulk(&self) -> usize {
         self.n_sides() * self.tiles.len()
     }
 
@@ -90,69 +95,337 @@ impl Region {
     }
 
     fn n_sides(&self) -> usize {
-        let mut bounds = None;
-
-        for &(x, y) in self.perimeter.keys() {
-            let (min_x, max_x, min_y, max_y) = bounds.get_or_insert((x, x, y, y));
-            *min_x = usize::min(*min_x, x);
-            *max_x = usize::max(*max_x, x);
-            *min_y = usize::min(*min_y, y);
-            *max_y = usize::max(*max_y, y);
-        }
+        self.corners
+    }
+}
 
-        let (min_x, max_x, min_y, max_y) = bounds.expect("No perimeter");
+/// Which regions border each other, and how much fence they share.
+#[derive(Debug)]
+struct RegionGraph {
+    regions: Vec<Region>,
+    region_of: BTreeMap<Coord, usize>,
+    /// Each bordering region with the length of fence between them
+    adjacent: BTreeMap<usize, Vec<(usize, usize)>>,
+    on_edge: BTreeSet<usize>,
+}
 
-        let xs = min_x..=max_x;
-        let ys = min_y..=max_y;
+impl RegionGraph {
+    pub fn new(map: &Map) -> Self {
+        let regions = find_regions(map);
 
-        let vert_sides = xs
-            .clone()
-            .map(|x| {
-                let mut cross_edges = 0;
+        let mut region_of = BTreeMap::new();
+        for (idx, region) in regions.iter().enumerate() {
+            region_of.extend(region.tiles.iter().map(|&c| (c, idx)));
+        }
 
-                let chunks = ys.clone().chunk_by(|&y| {
-                    let coord = (x, y);
-                    let direction = self
-                        .perimeter
-                        .get(&coord)
-                        .copied()
-                        .unwrap_or(Direction::empty());
+        let (max_x, max_y) = map
+            .keys()
+            .fold((0, 0), |(mx, my), &(x, y)| (mx.max(x), my.max(y)));
 
-                    if direction.contains(Direction::U) {
-                        cross_edges += 1;
+        let mut shared_fence = BTreeMap::new();
+        let mut on_edge = BTreeSet::new();
+
+        for (&(x, y), &a) in &region_of {
+            if x == 0 || y == 0 || x == max_x || y == max_y {
+                on_edge.insert(a);
+            }
+
+            for q in [(x + 1, y), (x, y + 1)] {
+                if let Some(&b) = region_of.get(&q) {
+                    if a != b {
+                        *shared_fence.entry((a.min(b), a.max(b))).or_insert(0) += 1;
                     }
+                }
+            }
+        }
 
-                    (direction.contains(Direction::L), cross_edges % 2)
-                });
+        let mut adjacent = BTreeMap::<_, Vec<_>>::new();
+        for ((a, b), len) in shared_fence {
+            adjacent.entry(a).or_default().push((b, len));
+            adjacent.entry(b).or_default().push((a, len));
+        }
 
-                chunks.into_iter().filter(|&((v, _), _)| v).count()
-            })
-            .sum::<usize>();
+        Self {
+            regions,
+            region_of,
+            adjacent,
+            on_edge,
+        }
+    }
 
-        let horz_sides = ys
-            .map(|y| {
-                let mut cross_edges = 0;
+    pub fn region_at(&self, coord: Coord) -> Option<usize> {
+        self.region_of.get(&coord).copied()
+    }
 
-                let chunks = xs.clone().chunk_by(|&x| {
-                    let coord = (x, y);
-                    let direction = self
-                        .perimeter
-                        .get(&coord)
-                        .copied()
-                        .unwrap_or(Direction::empty());
+    /// Each bordering region with the length of fence between them.
+    pub fn neighbors(&self, region: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.adjacent.get(&region).into_iter().flatten().copied()
+    }
 
-                    if direction.contains(Direction::L) {
-                        cross_edges += 1;
-                    }
+    pub fn touching_edge(&self) -> &BTreeSet<usize> {
+        &self.on_edge
+    }
 
-                    (direction.contains(Direction::U), cross_edges % 2)
-                });
+    /// Regions that can't reach the edge of the grid without crossing
+    /// `region`.
+    pub fn enclosed_by(&self, region: usize) -> BTreeSet<usize> {
+        let mut reached = BTreeSet::new();
+        let mut to_visit = self
+            .on_edge
+            .iter()
+            .copied()
+            .filter(|&r| r != region)
+            .collect::<Vec<_>>();
+
+        while let Some(r) = to_visit.pop() {
+            if !reached.insert(r) {
+                continue;
+            }
 
-                chunks.into_iter().filter(|&((v, _), _)| v).count()
-            })
-            .sum::<usize>();
+            to_visit.extend(self.neighbors(r).map(|(n, _)| n).filter(|&n| n != region));
+        }
 
-        vert_sides + horz_sides
+        (0..self.regions.len())
+            .filter(|&r| r != region && !reached.contains(&r))
+            .collect()
+    }
+}
+
+/// A corner of a tile; tile `(x, y)` spans `(x, y)` to `(x + 1, y + 1)`.
+type Vertex = (usize, usize);
+
+/// A region's boundary as closed rings of corner vertices, with `y`
+/// growing downward. The outer ring runs clockwise as drawn and
+/// holes run counter-clockwise. Only the corners are kept.
+#[derive(Debug, Clone, PartialEq)]
+struct Polygon {
+    pub label: char,
+    pub outer: Vec<Vertex>,
+    pub holes: Vec<Vec<Vertex>>,
+}
+
+impl Region {
+    pub fn polygon(&self) -> Polygon {
+        let mut edges = self.boundary_edges();
+        let mut outer = None;
+        let mut holes = Vec::new();
+
+        while let Some((&start, _)) = edges.first_key_value() {
+            let ring = trace_ring(&mut edges, start);
+
+            if signed_area(&ring) > 0 {
+                assert!(outer.is_none(), "Region has multiple outer rings");
+                outer = Some(ring);
+            } else {
+                holes.push(ring);
+            }
+        }
+
+        Polygon {
+            label: self.label,
+            outer: outer.expect("Region has no outer ring"),
+            holes,
+        }
+    }
+
+    /// Each edge keeps the region on its right.
+    fn boundary_edges(&self) -> BTreeMap<Vertex, Vec<Vertex>> {
+        let mut edges = BTreeMap::<_, Vec<_>>::new();
+        let inside = |x: Option<usize>, y: Option<usize>| match (x, y) {
+            (Some(x), Some(y)) => self.tiles.contains(&(x, y)),
+            _ => false,
+        };
+
+        for &(x, y) in &self.tiles {
+            let sides = [
+                (inside(Some(x), y.checked_sub(1)), (x, y), (x + 1, y)),
+                (inside(Some(x + 1), Some(y)), (x + 1, y), (x + 1, y + 1)),
+                (inside(Some(x), Some(y + 1)), (x + 1, y + 1), (x, y + 1)),
+                (inside(x.checked_sub(1), Some(y)), (x, y + 1), (x, y)),
+            ];
+
+            for (shared, from, to) in sides {
+                if !shared {
+                    edges.entry(from).or_default().push(to);
+                }
+            }
+        }
+
+        edges
+    }
+}
+
+/// Follows edges from `start` until it returns, consuming them. Where
+/// the boundary touches itself at a vertex, turning left crosses over
+/// to this region's diagonal tile, so each hole stays its own ring
+/// that only touches the others at that point.
+fn trace_ring(edges: &mut BTreeMap<Vertex, Vec<Vertex>>, start: Vertex) -> Vec<Vertex> {
+    let heading = |a: Vertex, b: Vertex| {
+        let dx = b.0 as isize - a.0 as isize;
+        let dy = b.1 as isize - a.1 as isize;
+        (dx, dy)
+    };
+
+    let mut ring = vec![start];
+    let mut current = start;
+    let mut incoming: Option<(isize, isize)> = None;
+
+    loop {
+        let outgoing = edges.get_mut(&current).expect("Boundary is not closed");
+
+        let idx = match incoming {
+            Some((dx, dy)) if outgoing.len() > 1 => {
+                let left = (dy, -dx);
+                outgoing
+                    .iter()
+                    .position(|&next| heading(current, next) == left)
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        };
+
+        let next = outgoing.swap_remove(idx);
+        if outgoing.is_empty() {
+            edges.remove(&current);
+        }
+
+        incoming = Some(heading(current, next));
+        current = next;
+
+        if current == start {
+            break;
+        }
+        ring.push(current);
+    }
+
+    // Drop vertices in the middle of a straight run
+    let n = ring.len();
+    let corners = (0..n)
+        .filter(|&i| {
+            let prev = ring[(i + n - 1) % n];
+            let next = ring[(i + 1) % n];
+            heading(prev, ring[i]) != heading(ring[i], next)
+        })
+        .map(|i| ring[i]);
+    let mut ring = corners.collect::<Vec<_>>();
+
+    let min_idx = ring.iter().position_min().expect("Ring has no vertices");
+    ring.rotate_left(min_idx);
+    ring
+}
+
+/// Twice the area, positive for clockwise rings as drawn.
+fn signed_area(ring: &[Vertex]) -> isize {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let (x0, y0) = ring[i];
+            let (x1, y1) = ring[(i + 1) % n];
+            (x0 * y1) as isize - (x1 * y0) as isize
+        })
+        .sum()
+}
+
+fn region_polygons(s: &str) -> Vec<Polygon> {
+    let map = parse(s);
+    let regions = find_regions(&map);
+
+    regions.iter().map(Region::polygon).collect()
+}
+
+/// Draws one path per region, using the even-odd rule to cut out
+/// the holes. Each tile is `scale` units wide.
+fn to_svg(polygons: &[Polygon], scale: usize) -> String {
+    let (width, height) = polygons
+        .iter()
+        .flat_map(|p| &p.outer)
+        .fold((0, 0), |(w, h), &(x, y)| (w.max(x), h.max(y)));
+
+    let mut svg = String::new();
+    let (width, height) = (width * scale, height * scale);
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#,
+    )
+    .unwrap();
+
+    for polygon in polygons {
+        let mut d = String::new();
+        for ring in polygon.rings() {
+            for (i, &(x, y)) in ring.iter().enumerate() {
+                let cmd = if i == 0 { 'M' } else { 'L' };
+                write!(d, "{cmd}{} {} ", x * scale, y * scale).unwrap();
+            }
+            d.push('Z');
+        }
+
+        // Spread the labels around the color wheel
+        let hue = (u32::from(polygon.label) * 47) % 360;
+        writeln!(
+            svg,
+            r#"  <path d="{d}" fill="hsl({hue}, 60%, 70%)" fill-rule="evenodd" stroke="black"><title>{}</title></path>"#,
+            escape_xml(polygon.label),
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A `FeatureCollection` with one `Polygon` feature per region. Rings
+/// are closed by repeating the first vertex.
+fn to_geojson(polygons: &[Polygon]) -> String {
+    let features = polygons
+        .iter()
+        .map(|polygon| {
+            let rings = polygon
+                .rings()
+                .map(|ring| {
+                    let points = ring
+                        .iter()
+                        .chain(ring.first())
+                        .map(|(x, y)| format!("[{x},{y}]"))
+                        .join(",");
+                    format!("[{points}]")
+                })
+                .join(",");
+
+            format!(
+                r#"{{"type":"Feature","properties":{{"label":"{}"}},"geometry":{{"type":"Polygon","coordinates":[{rings}]}}}}"#,
+                escape_json(polygon.label),
+            )
+        })
+        .join(",");
+
+    format!(r#"{{"type":"FeatureCollection","features":[{features}]}}"#)
+}
+
+impl Polygon {
+    fn rings(&self) -> impl Iterator<Item = &Vec<Vertex>> {
+        std::iter::once(&self.outer).chain(&self.holes)
+    }
+}
+
+/// Safe to use as XML text or inside a quoted attribute.
+fn escape_xml(c: char) -> String {
+    match c {
+        '&' => "&amp;".into(),
+        '<' => "&lt;".into(),
+        '>' => "&gt;".into(),
+        '"' => "&quot;".into(),
+        '\'' => "&apos;".into(),
+        c => c.into(),
+    }
+}
+
+/// Safe to use inside a JSON string.
+fn escape_json(c: char) -> String {
+    match c {
+        '"' => r#"\""#.into(),
+        '\\' => r"\\".into(),
+        c if u32::from(c) < 0x20 => format!(r"\u{:04x}", u32::from(c)),
+        c => c.into(),
     }
 }
 
@@ -164,6 +437,12 @@ fn explore(map: &Map, coord: Coord, visited: &mut BTreeSet<Coord>, regions: &mut
     let label = map[&coord];
     let mut tiles = BTreeSet::new();
     let mut perimeter = BTreeMap::new();
+    let mut corners = 0;
+
+    let same = |(x, y): Coord, (dx, dy): (isize, isize)| {
+        let q = x.checked_add_signed(dx).zip(y.checked_add_signed(dy));
+        q.and_then(|q| map.get(&q)) == Some(&label)
+    };
 
     let mut to_explore = BTreeSet::from_iter([coord]);
 
@@ -175,6 +454,19 @@ fn explore(map: &Map, coord: Coord, visited: &mut BTreeSet<Coord>, regions: &mut
 
         tiles.insert(coord);
 
+        // Look at each corner of this tile from the inside
+        for (dx, dy) in [(-1, -1), (1, -1), (1, 1), (-1, 1)] {
+            let horz = same(coord, (dx, 0));
+            let vert = same(coord, (0, dy));
+            let diag = same(coord, (dx, dy));
+
+            let outside_corner = !horz && !vert;
+            let inside_corner = horz && vert && !diag;
+            if outside_corner || inside_corner {
+                corners += 1;
+            }
+        }
+
         let (x, y) = coord;
 
         match y.checked_sub(1) {
@@ -268,6 +560,7 @@ fn explore(map: &Map, coord: Coord, visited: &mut BTreeSet<Coord>, regions: &mut
         label,
         tiles,
         perimeter,
+        corners,
     });
 }
 
@@ -324,4 +617,126 @@ mod test {
     fn example_5_bulk() {
         assert_eq!(368, fence_cost_bulk(EXAMPLE_5));
     }
+
+    #[test]
+    fn diagonal_bulk() {
+        // Same-label tiles that only touch at a corner are separate regions
+        assert_eq!(16, fence_cost_bulk("AB\nBA"));
+        assert_eq!(7 * 10 + 4 + 4, fence_cost_bulk("AAA\nABA\nAAB"));
+    }
+
+    #[test]
+    fn polygons() {
+        let polygons = region_polygons(EXAMPLE_1);
+        let a = polygons.iter().find(|p| p.label == 'A').unwrap();
+        assert_eq!(vec![(0, 0), (4, 0), (4, 1), (0, 1)], a.outer);
+        assert!(a.holes.is_empty());
+
+        let polygons = region_polygons(EXAMPLE_2);
+        let o = polygons.iter().find(|p| p.label == 'O').unwrap();
+        assert_eq!(4, o.holes.len());
+        assert_eq!(vec![(1, 1), (1, 2), (2, 2), (2, 1)], o.holes[0]);
+
+        // The B regions touch diagonally, but are still separate holes
+        let polygons = region_polygons(EXAMPLE_5);
+        let a = polygons.iter().find(|p| p.label == 'A').unwrap();
+        assert_eq!(2, a.holes.len());
+        assert!(a.holes.iter().all(|h| h.len() == 4));
+
+        // A hole that touches the outside at a corner
+        let polygons = region_polygons("AAA\nABA\nAAB");
+        let a = polygons.iter().find(|p| p.label == 'A').unwrap();
+        assert_eq!(
+            vec![(0, 0), (3, 0), (3, 2), (2, 2), (2, 3), (0, 3)],
+            a.outer
+        );
+        assert_eq!(vec![vec![(1, 1), (1, 2), (2, 2), (2, 1)]], a.holes);
+    }
+
+    #[test]
+    fn polygon_corners_are_sides() {
+        for example in [EXAMPLE_1, EXAMPLE_2, EXAMPLE_3, EXAMPLE_4, EXAMPLE_5] {
+            let map = parse(example);
+            for region in find_regions(&map) {
+                let polygon = region.polygon();
+                let corners = polygon.rings().map(Vec::len).sum::<usize>();
+                assert_eq!(region.n_sides(), corners);
+            }
+        }
+    }
+
+    #[test]
+    fn export() {
+        let polygons = region_polygons(EXAMPLE_2);
+
+        let svg = to_svg(&polygons, 10);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"<path d="M0 0 L50 0 L50 50 L0 50 ZM10 10 L10 20 L20 20 L20 10 Z"#));
+        assert_eq!(5, svg.matches("<path").count());
+
+        let geojson = to_geojson(&polygons[1..2]);
+        assert_eq!(
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{"label":"X"},"geometry":{"type":"Polygon","coordinates":[[[1,1],[2,1],[2,2],[1,2],[1,1]]]}}]}"#,
+            geojson
+        );
+    }
+
+    #[test]
+    fn export_escapes_labels() {
+        let polygon = |label| Polygon {
+            label,
+            outer: vec![(0, 0), (1, 0), (1, 1), (0, 1)],
+            holes: vec![],
+        };
+        let polygons = ['<', '&', '"', '\'', '\\', '\t'].map(polygon);
+
+        let svg = to_svg(&polygons, 1);
+        for title in ["&lt;", "&amp;", "&quot;", "&apos;", "\\", "\t"] {
+            assert!(svg.contains(&format!("<title>{title}</title>")), "{title}");
+        }
+
+        let geojson = to_geojson(&polygons);
+        for label in ["<", "&", r#"\""#, "'", r"\\", r"\u0009"] {
+            let property = format!(r#"{{"label":"{label}"}}"#);
+            assert!(geojson.contains(&property), "{label}");
+        }
+    }
+
+    #[test]
+    fn region_graph() {
+        let graph = RegionGraph::new(&parse(EXAMPLE_2));
+        let o = graph.region_at((0, 0)).unwrap();
+        let x = graph.region_at((1, 1)).unwrap();
+
+        assert_eq!(vec![(o, 4)], graph.neighbors(x).collect::<Vec<_>>());
+        assert_eq!(4, graph.neighbors(o).count());
+        assert_eq!(&BTreeSet::from([o]), graph.touching_edge());
+        assert_eq!(4, graph.enclosed_by(o).len());
+        assert!(graph.enclosed_by(x).is_empty());
+
+        let graph = RegionGraph::new(&parse(EXAMPLE_1));
+        let c = graph.region_at((2, 1)).unwrap();
+        let d = graph.region_at((3, 1)).unwrap();
+        let e = graph.region_at((0, 3)).unwrap();
+        assert_eq!(
+            vec![1, 2, 2, 2],
+            graph
+                .neighbors(c)
+                .map(|(_, len)| len)
+                .sorted()
+                .collect::<Vec<_>>()
+        );
+        assert!(graph.neighbors(d).all(|(n, _)| n != e));
+        assert!(graph.enclosed_by(c).is_empty());
+
+        // The B regions only touch diagonally, but neither escapes
+        let graph = RegionGraph::new(&parse(EXAMPLE_5));
+        let a = graph.region_at((0, 0)).unwrap();
+        assert_eq!(2, graph.enclosed_by(a).len());
+
+        let graph = RegionGraph::new(&parse("AAA\nABA\nAAB"));
+        let a = graph.region_at((0, 0)).unwrap();
+        let b = graph.region_at((1, 1)).unwrap();
+        assert_eq!(BTreeSet::from([b]), graph.enclosed_by(a));
+    }
 }
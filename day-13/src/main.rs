@@ -13,139 +13,326 @@ fn main() {
 }
 
 fn minimum_tokens(s: &str) -> u64 {
-    parse(s).flat_map(|b| b.minimum_tokens()).sum()
+    total_cost(parse(s).map(|m| m.with_limit(100)))
 }
 
 fn minimum_tokens_fixed(s: &str) -> u64 {
-    parse(s).flat_map(|b| b.minimum_tokens_fixed()).sum()
+    const OFFSET: u64 = 10000000000000;
+
+    total_cost(parse(s).map(|m| m.with_offset(OFFSET)))
 }
 
-fn parse(s: &str) -> impl Iterator<Item = Behavior> + '_ {
-    let parse_coord = |x: &str, y: &str| {
-        let x = x.parse::<u64>().expect("X not a number");
-        let y = y.parse::<u64>().expect("Y not a number");
-        (x, y)
-    };
+fn total_cost(machines: impl Iterator<Item = Machine>) -> u64 {
+    machines.flat_map(|m| m.solve()).map(|s| s.cost).sum()
+}
+
+/// Any number of buttons may come before the prize. A button may be
+/// annotated with its cost and, optionally, the most times it can be
+/// pressed: `Button C: X+1, Y+2 (cost 5, max 100)`. Otherwise button
+/// A costs 3 and the rest cost 1. Lines may end with CRLF.
+fn parse(s: &str) -> impl Iterator<Item = Machine> + '_ {
+    let button_regex =
+        Regex::new(r"^Button (.): X\+(\d+), Y\+(\d+)(?: \(cost (\d+)(?:, max (\d+))?\))?$")
+            .expect("Invalid button regex");
+    let prize_regex = Regex::new(r"^Prize: X=(\d+), Y=(\d+)$").expect("Invalid prize regex");
+
+    let number =
+        |m: Option<regex::Match>| m.map(|m| m.as_str().parse::<u64>().expect("Not a number"));
+
+    blank_line_groups(s).into_iter().map(move |g| {
+        let mut buttons = Vec::new();
+        let mut prize = None;
+
+        for l in g.into_iter().map(str::trim_end) {
+            if let Some(c) = button_regex.captures(l) {
+                let label = c[1].chars().next().expect("Missing label");
+                let movement = (number(c.get(2)).unwrap(), number(c.get(3)).unwrap());
+                let default_cost = if label == 'A' { A_COST } else { B_COST };
+                let cost = number(c.get(4)).unwrap_or(default_cost);
+                let limit = number(c.get(5));
+
+                buttons.push(Button {
+                    label,
+                    movement,
+                    cost,
+                    limit,
+                });
+            } else if let Some(c) = prize_regex.captures(l) {
+                assert!(prize.is_none(), "Multiple prizes");
+                prize = Some((number(c.get(1)).unwrap(), number(c.get(2)).unwrap()));
+            } else {
+                panic!("Unknown line {l}");
+            }
+        }
+
+        let prize = prize.expect("Missing prize");
+        Machine { buttons, prize }
+    })
+}
+
+/// The lines between blank lines, with either LF or CRLF endings.
+fn blank_line_groups(s: &str) -> Vec<Vec<&str>> {
+    let lines = s.lines().collect::<Vec<_>>();
 
-    let button_regex = Regex::new(r"Button .: X\+(\d+), Y\+(\d+)").expect("Invalid button regex");
-    let parse_button = move |btn: &str| {
-        let (_, [x, y]) = button_regex
-            .captures(btn)
-            .expect("Match not found")
-            .extract();
-        parse_coord(x, y)
+    lines
+        .split(|l| l.trim_end().is_empty())
+        .filter(|g| !g.is_empty())
+        .map(<[_]>::to_vec)
+        .collect()
+}
+
+const A_COST: u64 = 3;
+const B_COST: u64 = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Button {
+    label: char,
+    movement: (u64, u64),
+    cost: u64,
+    limit: Option<u64>,
+}
+
+impl Button {
+    /// Changes nothing and costs nothing
+    const IDLE: Self = Self {
+        label: '_',
+        movement: (0, 0),
+        cost: 0,
+        limit: Some(0),
     };
 
-    let prize_regex = Regex::new(r"Prize: X=(\d+), Y=(\d+)").expect("Invalid prize regex");
-    let parse_prize = move |prz: &str| {
-        let (_, [x, y]) = prize_regex
-            .captures(prz)
-            .expect("Match not found")
-            .extract();
-        parse_coord(x, y)
+    fn limit(&self) -> Option<i128> {
+        self.limit.map(i128::from)
+    }
+}
+
+/// Finds the cheapest presses of two buttons that exactly reach `prize`.
+fn solve_pair(a: &Button, b: &Button, prize: (i128, i128)) -> Option<(i128, i128)> {
+    let (a_x, a_y) = a.movement;
+    let (b_x, b_y) = b.movement;
+    let [a_x, a_y, b_x, b_y] = [a_x, a_y, b_x, b_y].map(i128::from);
+    let (p_x, p_y) = prize;
+
+    // n_a * a_x + n_b * b_x = p_x
+    // n_a * a_y + n_b * b_y = p_y
+    let det = a_x * b_y - a_y * b_x;
+
+    let (n_a, n_b) = if det != 0 {
+        // Cramer's rule; exactly one real solution
+        let n_a = exact_div(p_x * b_y - p_y * b_x, det)?;
+        let n_b = exact_div(a_x * p_y - a_y * p_x, det)?;
+        (n_a, n_b)
+    } else {
+        // The buttons are collinear (or zero) so the prize must
+        // be on the same line. Project everything onto whichever
+        // axis the buttons move along.
+        let (a_k, b_k, p_k) = if a_x != 0 || b_x != 0 {
+            (a_x, b_x, p_x)
+        } else {
+            (a_y, b_y, p_y)
+        };
+
+        let costs = [a.cost, b.cost].map(i128::from);
+        let limits = [a.limit(), b.limit()];
+        let (n_a, n_b) = cheapest_on_line(a_k, b_k, p_k, costs, limits)?;
+
+        let fits = n_a * a_x + n_b * b_x == p_x && n_a * a_y + n_b * b_y == p_y;
+        fits.then_some((n_a, n_b))?
     };
 
-    s.split("\n\n").map(move |g| {
-        let mut l = g.lines();
+    let in_range = |n, limit: Option<i128>| n >= 0 && limit.is_none_or(|l| n <= l);
+    (in_range(n_a, a.limit()) && in_range(n_b, b.limit())).then_some((n_a, n_b))
+}
+
+/// Minimises the cost of `n_a * a + n_b * b = p` with `0 <= n <= limit`,
+/// where a missing limit leaves `n` unbounded above. The button
+/// movements must not be negative.
+fn cheapest_on_line(
+    a: i128,
+    b: i128,
+    p: i128,
+    [cost_a, cost_b]: [i128; 2],
+    [limit_a, limit_b]: [Option<i128>; 2],
+) -> Option<(i128, i128)> {
+    match (a, b) {
+        (0, 0) => return (p == 0).then_some((0, 0)),
+        // Pressing a button that doesn't move is a waste
+        (0, _) => return Some((0, exact_div(p, b)?)),
+        (_, 0) => return Some((exact_div(p, a)?, 0)),
+        _ => {}
+    }
 
-        let a = l.next().expect("Missing A");
-        let b = l.next().expect("Missing B");
-        let p = l.next().expect("Missing prize");
+    let (g, x, y) = extended_gcd(a, b);
+    let scale = exact_div(p, g)?;
+    let (n_a, n_b) = (x * scale, y * scale);
 
-        let a = parse_button(a);
-        let b = parse_button(b);
-        let prize = parse_prize(p);
+    // Every solution is (n_a + k * step_a, n_b - k * step_b)
+    let step_a = b / g;
+    let step_b = a / g;
 
-        Behavior { a, b, prize }
-    })
+    let mut k_min = div_ceil(-n_a, step_a);
+    if let Some(limit_b) = limit_b {
+        k_min = k_min.max(div_ceil(n_b - limit_b, step_b));
+    }
+
+    let mut k_max = div_floor(n_b, step_b);
+    if let Some(limit_a) = limit_a {
+        k_max = k_max.min(div_floor(limit_a - n_a, step_a));
+    }
+
+    if k_min > k_max {
+        return None;
+    }
+
+    // Cost is linear in k, so one of the ends is cheapest
+    let slope = step_a * cost_a - step_b * cost_b;
+    let k = if slope > 0 { k_min } else { k_max };
+
+    Some((n_a + k * step_a, n_b - k * step_b))
 }
 
-#[derive(Debug)]
-struct Behavior {
-    a: (u64, u64),
-    b: (u64, u64),
+/// A claw machine with any number of buttons.
+#[derive(Debug, Clone, PartialEq)]
+struct Machine {
+    buttons: Vec<Button>,
     prize: (u64, u64),
 }
 
-impl Behavior {
-    fn minimum_tokens(&self) -> Option<u64> {
-        // n_a * a_x + n_b * b_x = p_x
-        // n_a * a_y + n_b * b_y = p_y
-        //
-        // n_a * (a_x + a_y) + n_b * (b_x + b_y) = p_x + p_y
-        // n_a * a_k         + n_b * b_k         = p
-
-        let Self {
-            a: (a_x, a_y),
-            b: (b_x, b_y),
-            prize: (p_x, p_y),
-        } = *self;
-
-        let a_k = a_x + a_y;
-        let b_k = b_x + b_y;
-        let p = p_x + p_y;
-
-        (0..=100)
-            .flat_map(|n_a| {
-                let leftover = p.checked_sub(n_a * a_k)?;
-                if leftover % b_k == 0 {
-                    let n_b = leftover / b_k;
-                    Some((n_a, n_b))
-                } else {
-                    None
+#[derive(Debug, Clone, PartialEq)]
+struct Solution {
+    /// In the same order as the machine's buttons
+    presses: Vec<u64>,
+    cost: u64,
+}
+
+impl Machine {
+    /// No button may be pressed more than `limit` times.
+    fn with_limit(mut self, limit: u64) -> Self {
+        for button in &mut self.buttons {
+            button.limit = Some(button.limit.map_or(limit, |l| l.min(limit)));
+        }
+        self
+    }
+
+    /// Moves the prize by `offset` in both directions.
+    fn with_offset(mut self, offset: u64) -> Self {
+        let (p_x, p_y) = self.prize;
+        self.prize = (p_x + offset, p_y + offset);
+        self
+    }
+
+    /// Tries every press count of all but two buttons, then solves
+    /// those two exactly. This is exponential in the number of extra
+    /// buttons and is only fast when their limits are small. Unlimited
+    /// buttons are solved exactly where possible; any others can't be
+    /// pressed more often than it takes to pass the prize.
+    fn solve(&self) -> Option<Solution> {
+        // Stable, so limited buttons keep their order ahead of the
+        // unlimited ones
+        let mut order = (0..self.buttons.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| self.buttons[i].limit.is_none());
+
+        let mut buttons = order.iter().map(|&i| self.buttons[i]).collect::<Vec<_>>();
+        while buttons.len() < 2 {
+            buttons.push(Button::IDLE);
+        }
+
+        let (p_x, p_y) = self.prize;
+        let prize = (i128::from(p_x), i128::from(p_y));
+
+        let mut presses = vec![0; buttons.len()];
+        let mut best = None;
+        search(&buttons, prize, 0, &mut presses, &mut best);
+
+        let (cost, found) = best?;
+
+        let mut presses = vec![0; self.buttons.len()];
+        for (&i, n) in order.iter().zip(found) {
+            presses[i] = u64::try_from(n).expect("Negative presses");
+        }
+        let cost = u64::try_from(cost).ok()?;
+
+        Some(Solution { presses, cost })
+    }
+}
+
+fn search(
+    buttons: &[Button],
+    remaining: (i128, i128),
+    cost: i128,
+    presses: &mut Vec<i128>,
+    best: &mut Option<(i128, Vec<i128>)>,
+) {
+    let idx = presses.len() - buttons.len();
+
+    match buttons {
+        [a, b] => {
+            if let Some((n_a, n_b)) = solve_pair(a, b, remaining) {
+                let cost = cost + n_a * i128::from(a.cost) + n_b * i128::from(b.cost);
+                if !too_expensive(best, cost) {
+                    presses[idx] = n_a;
+                    presses[idx + 1] = n_b;
+                    *best = Some((cost, presses.clone()));
                 }
-            })
-            .filter(|&(n_a, n_b)| n_a <= 100 && n_b <= 100)
-            .filter(|&(n_a, n_b)| n_a * a_x + n_b * b_x == p_x)
-            .filter(|&(n_a, n_b)| n_a * a_y + n_b * b_y == p_y)
-            .map(|(n_a, n_b)| n_a * 3 + n_b)
-            .min()
+            }
+        }
+
+        [button, rest @ ..] => {
+            let (m_x, m_y) = button.movement;
+            let (m_x, m_y) = (i128::from(m_x), i128::from(m_y));
+            let (r_x, r_y) = remaining;
+
+            // Can't overshoot the prize
+            let mut max = button.limit().unwrap_or(i128::MAX);
+            if m_x > 0 {
+                max = max.min(r_x / m_x);
+            }
+            if m_y > 0 {
+                max = max.min(r_y / m_y);
+            }
+            if m_x == 0 && m_y == 0 {
+                max = 0;
+            }
+
+            for n in 0..=max {
+                let cost = cost + n * i128::from(button.cost);
+                if too_expensive(best, cost) {
+                    break;
+                }
+
+                presses[idx] = n;
+                search(rest, (r_x - n * m_x, r_y - n * m_y), cost, presses, best);
+            }
+            presses[idx] = 0;
+        }
+
+        [] => unreachable!("Always at least two buttons"),
     }
+}
+
+fn too_expensive(best: &Option<(i128, Vec<i128>)>, cost: i128) -> bool {
+    best.as_ref().is_some_and(|&(best, _)| cost >= best)
+}
 
-    fn minimum_tokens_fixed(&self) -> Option<u64> {
-        let Self {
-            a: (a_x, a_y),
-            b: (b_x, b_y),
-            prize: (p_x, p_y),
-        } = *self;
-
-        const OFFSET: u64 = 10000000000000;
-        let p_x = p_x + OFFSET;
-        let p_y = p_y + OFFSET;
-
-        assert_ne!(a_x, 0);
-        assert_ne!(a_y, 0);
-        assert_ne!(b_x, 0);
-        assert_ne!(b_y, 0);
-        assert_ne!(a_x * b_y, a_y * b_x);
-
-        // n_a * a_x + n_b * b_x = p_x
-        // n_a * a_y + n_b * b_y = p_y
-        //
-        // Solve for n_a...
-        //
-        // n_a = (p_x * b_y - p_y * b_x) / (a_x * b_y - b_x * a_y)
-        //
-        // Then solve for n_b...
-        //
-        // n_b = (p_x - n_a * a_x) / b_x;
-
-        // Should we ensure if *both* are negative?
-        let n_a_n = (p_x * b_y).abs_diff(p_y * b_x);
-        let n_a_d = (a_x * b_y).abs_diff(a_y * b_x);
-
-        let n_a = evenly_divide(n_a_n, n_a_d)?;
-
-        let n_b_n = p_x - n_a * a_x;
-        let n_b_d = b_x;
-
-        let n_b = evenly_divide(n_b_n, n_b_d)?;
-
-        Some(n_a * 3 + n_b)
+/// Returns `(g, x, y)` where `a * x + b * y = g`.
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
     }
 }
 
-fn evenly_divide(n: u64, d: u64) -> Option<u64> {
+fn div_floor(n: i128, d: i128) -> i128 {
+    n.div_euclid(d)
+}
+
+fn div_ceil(n: i128, d: i128) -> i128 {
+    -(-n).div_euclid(d)
+}
+
+fn exact_div(n: i128, d: i128) -> Option<i128> {
     (n % d == 0).then_some(n / d)
 }
 
@@ -159,4 +346,177 @@ mod test {
     fn example() {
         assert_eq!(480, minimum_tokens(EXAMPLE));
     }
+
+    #[test]
+    fn example_crlf() {
+        // Every line ends in CRLF, including the last
+        let crlf = format!("{EXAMPLE}\n").replace('\n', "\r\n");
+        assert!(crlf.split_inclusive('\n').all(|l| l.ends_with("\r\n")));
+        assert_eq!(480, minimum_tokens(&crlf));
+
+        // Mixed endings and extra blank lines
+        let mixed = EXAMPLE.replacen("\n\n", "\r\n\n\r\n", 1);
+        assert_eq!(480, minimum_tokens(&mixed));
+        assert_eq!(4, parse(&format!("{crlf}\r\n\r\n")).count());
+    }
+
+    #[test]
+    fn solver() {
+        let machines = parse(EXAMPLE).collect::<Vec<_>>();
+        assert_eq!(
+            Some(Solution {
+                presses: vec![80, 40],
+                cost: 280
+            }),
+            machines[0].clone().with_limit(100).solve(),
+        );
+
+        let fixed = machines
+            .into_iter()
+            .map(|m| m.with_offset(10000000000000).solve().is_some())
+            .collect::<Vec<_>>();
+        assert_eq!([false, true, false, true], &fixed[..]);
+    }
+
+    #[test]
+    fn degenerate_machines() {
+        let machine = |a, b, prize| {
+            let button = |label, movement, cost| Button {
+                label,
+                movement,
+                cost,
+                limit: None,
+            };
+            Machine {
+                buttons: vec![button('A', a, A_COST), button('B', b, B_COST)],
+                prize,
+            }
+        };
+        let cost = |m: Machine| m.solve().map(|s| (s.presses[0], s.presses[1], s.cost));
+
+        // Collinear; B is cheaper per unit
+        assert_eq!(Some((0, 10, 10)), cost(machine((2, 2), (1, 1), (10, 10))));
+        // Collinear; A is cheaper per unit
+        assert_eq!(Some((2, 2, 8)), cost(machine((4, 4), (1, 1), (10, 10))));
+        // Collinear, but the prize is off the line
+        assert_eq!(None, cost(machine((1, 1), (2, 2), (3, 4))));
+        // Collinear, but not an integer combination
+        assert_eq!(None, cost(machine((2, 2), (4, 4), (3, 3))));
+
+        // Zero components
+        assert_eq!(Some((2, 2, 8)), cost(machine((0, 5), (3, 0), (6, 10))));
+        assert_eq!(Some((0, 3, 3)), cost(machine((0, 0), (1, 2), (3, 6))));
+        assert_eq!(Some((0, 0, 0)), cost(machine((0, 0), (0, 0), (0, 0))));
+
+        // Would need a negative number of presses
+        assert_eq!(None, cost(machine((1, 2), (2, 1), (1, 5))));
+
+        // Collinear, and the first solution found on the line has a
+        // negative number of presses
+        assert_eq!(Some((1, 1, 4)), cost(machine((3, 3), (2, 2), (5, 5))));
+
+        // Limits apply on the collinear line too
+        let m = machine((4, 4), (1, 1), (40, 40));
+        assert_eq!(Some((10, 0, 30)), cost(m.clone()));
+        assert_eq!(Some((8, 8, 32)), cost(m.clone().with_limit(8)));
+        assert_eq!(None, cost(m.with_limit(4)));
+    }
+
+    #[test]
+    fn many_buttons() {
+        // Same answers as the two-button machines
+        let limited = EXAMPLE
+            .lines()
+            .map(|l| match l.split(':').next() {
+                Some("Button A") => format!("{l} (cost 3, max 100)"),
+                Some("Button B") => format!("{l} (cost 1, max 100)"),
+                _ => l.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(480, total_cost(parse(&limited)));
+        assert_eq!(480, total_cost(parse(EXAMPLE)));
+
+        let machines = parse(
+            "Button A: X+3, Y+3\n\
+             Button B: X+1, Y+1 (cost 2)\n\
+             Button C: X+5, Y+0 (cost 1, max 1)\n\
+             Prize: X=10, Y=5",
+        )
+        .collect::<Vec<_>>();
+        let machine = &machines[0];
+        assert_eq!(3, machine.buttons.len());
+        assert_eq!(Some(1), machine.buttons[2].limit);
+
+        // A: 3 per unit, B: 2 per unit. C has to take the extra X.
+        assert_eq!(
+            Some(Solution {
+                presses: vec![1, 2, 1],
+                cost: 8
+            }),
+            machine.solve(),
+        );
+
+        let one = Machine {
+            buttons: vec![Button {
+                label: 'A',
+                movement: (2, 3),
+                cost: 4,
+                limit: None,
+            }],
+            prize: (6, 9),
+        };
+        assert_eq!(
+            Some(Solution {
+                presses: vec![3],
+                cost: 12
+            }),
+            one.solve()
+        );
+
+        let none = Machine {
+            buttons: vec![],
+            prize: (0, 0),
+        };
+        assert_eq!(Some(0), none.solve().map(|s| s.cost));
+
+        // Unlimited buttons are solved exactly, even when listed
+        // first, so a distant prize doesn't mean searching every
+        // press count
+        let machine = parse(
+            "Button A: X+94, Y+34\n\
+             Button C: X+1, Y+0 (cost 1, max 3)\n\
+             Button B: X+22, Y+67\n\
+             Prize: X=8400, Y=5400",
+        )
+        .next()
+        .unwrap();
+        assert_eq!(
+            Some(Solution {
+                presses: vec![80, 0, 40],
+                cost: 280
+            }),
+            machine.clone().solve(),
+        );
+        let far = machine.with_offset(10000000000000).solve();
+        assert_eq!(None, far);
+
+        // Only the last two unlimited buttons are solved exactly; the
+        // others are bounded by the prize
+        let machine = parse(
+            "Button A: X+1, Y+1\n\
+             Button B: X+2, Y+0\n\
+             Button C: X+0, Y+2\n\
+             Prize: X=3, Y=3",
+        )
+        .next()
+        .unwrap();
+        assert_eq!(
+            Some(Solution {
+                presses: vec![1, 1, 1],
+                cost: 5
+            }),
+            machine.solve(),
+        );
+    }
 }
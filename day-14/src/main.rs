@@ -1,9 +1,15 @@
-use std::collections::BTreeMap;
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::Range,
+};
 
 use itertools::Itertools;
 
 const INPUT: &str = include_str!("../input.txt");
-const DIMENSIONS: [i32; 2] = [101, 103];
 
 fn main() {
     assert_eq!(226179492, safety_factor(INPUT, DIMENSIONS, 100));
@@ -16,61 +22,319 @@ fn main() {
     assert_eq!(7502, part_2);
 }
 
-fn safety_factor(s: &str, dimensions: [i32; 2], seconds: i32) -> usize {
-    let mut quads = BTreeMap::new();
+const DIMENSIONS: [i32; 2] = [101, 103];
 
-    for l in s.lines() {
-        let (p, v) = parse(l);
+fn safety_factor(s: &str, dimensions: [i32; 2], seconds: i64) -> usize {
+    let robots = s.lines().map(parse).collect::<Vec<_>>();
+    let positions = positions_at(&robots, dimensions, seconds);
 
-        let n1 = simulate(dimensions, p, v, seconds);
+    Partition::quadrants(dimensions).safety_factor(&positions)
+}
 
-        if let Some(q) = Quadrant::categorize(dimensions, n1) {
-            *quads.entry(q).or_insert(0) += 1;
-        }
+fn search(s: &str, dimensions: [i32; 2]) -> usize {
+    let robots = s.lines().map(parse).collect::<Vec<_>>();
+
+    // Any row/column completely filled?
+    // Not in 1st million
+    //
+    // Any row/column completely empty?
+    // Quite a few in first 1M
+    //
+    // Symmetric around X/Y axis?
+    // Not obviously useful
+
+    // Any rows of 10+ contiguous robots?
+    let seconds =
+        RowRun::first_longer_than(&robots, dimensions, 10).expect("Did not find a result");
+
+    print(dimensions, &configuration_at(&robots, dimensions, seconds));
+    seconds as usize
+}
+
+type Robot = ([i32; 2], [i32; 2]);
+
+fn positions_at(robots: &[Robot], dimensions: [i32; 2], seconds: i64) -> Vec<[i32; 2]> {
+    robots
+        .iter()
+        .map(|&(p, v)| simulate(dimensions, p, v, seconds))
+        .collect()
+}
+
+/// How many robots are on each tile after `seconds`, computed directly.
+fn configuration_at(
+    robots: &[Robot],
+    dimensions: [i32; 2],
+    seconds: i64,
+) -> BTreeMap<[i32; 2], i32> {
+    let mut pos = BTreeMap::new();
+    for p in positions_at(robots, dimensions, seconds) {
+        *pos.entry(p).or_insert(0) += 1;
     }
+    pos
+}
 
-    quads.values().product()
+/// After this many seconds, every robot is back where it started.
+/// Each robot wraps around each axis on its own schedule; this is the
+/// lcm of all of them.
+fn period(robots: &[Robot], dimensions: [i32; 2]) -> i64 {
+    robots
+        .iter()
+        .flat_map(|(_, v)| {
+            [0, 1].map(|axis| {
+                let size = i64::from(dimensions[axis]);
+                size / gcd(i64::from(v[axis]).rem_euclid(size), size)
+            })
+        })
+        .fold(1, lcm)
 }
 
-fn search(s: &str, dimensions: [i32; 2]) -> usize {
-    let mut robots = s.lines().map(parse).collect::<Vec<_>>();
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
 
-    let mut pos = BTreeMap::new();
+fn lcm(a: i64, b: i64) -> i64 {
+    a / gcd(a, b) * b
+}
 
-    // loop {
-    for k in 0..1_000_000 {
-        pos.clear();
+/// Each distinct configuration within one period, with the first time
+/// it occurs. Robots can swap places, so a configuration may repeat
+/// before the period is over.
+fn distinct_states(
+    robots: &[Robot],
+    dimensions: [i32; 2],
+) -> impl Iterator<Item = (i64, BTreeMap<[i32; 2], i32>)> + '_ {
+    let mut seen = BTreeSet::new();
+
+    (0..period(robots, dimensions)).filter_map(move |t| {
+        let state = configuration_at(robots, dimensions, t);
+        seen.insert(state.clone()).then_some((t, state))
+    })
+}
 
-        for (p, v) in &mut robots {
-            *p = simulate(dimensions, *p, *v, 1);
-            *pos.entry(*p).or_insert(0) += 1;
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Detection {
+    pub seconds: i64,
+    /// How many standard deviations the best candidate stands out from
+    /// the rest.
+    pub confidence: f64,
+}
+
+trait Detector: fmt::Display {
+    fn detect(&self, robots: &[Robot], dimensions: [i32; 2]) -> Option<Detection>;
+}
+
+/// Runs each detector over the robots.
+fn detect_all<'a>(
+    robots: &[Robot],
+    dimensions: [i32; 2],
+    detectors: &[&'a dyn Detector],
+) -> Vec<(&'a dyn Detector, Option<Detection>)> {
+    detectors
+        .iter()
+        .map(|&d| (d, d.detect(robots, dimensions)))
+        .collect()
+}
+
+const DETECTORS: &[&dyn Detector] = &[&RowRun, &Variance, &Entropy { cell: [5, 5] }, &Crt];
+
+/// Picks the time with the highest score.
+fn best_of(scores: impl IntoIterator<Item = (i64, f64)>) -> Option<Detection> {
+    let scores = scores.into_iter().collect::<Vec<_>>();
+    let n = scores.len() as f64;
+
+    let &(seconds, best) = scores.iter().max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    let mean = scores.iter().map(|(_, s)| s).sum::<f64>() / n;
+    let variance = scores.iter().map(|(_, s)| (s - mean).powi(2)).sum::<f64>() / n;
+    let std_dev = variance.sqrt();
+
+    let confidence = if std_dev == 0.0 {
+        0.0
+    } else {
+        (best - mean) / std_dev
+    };
+
+    Some(Detection {
+        seconds,
+        confidence,
+    })
+}
+
+/// The longest horizontal line of robots.
+struct RowRun;
+
+impl RowRun {
+    /// The first time within one period that some row has a line of
+    /// more than `len` robots. Stops looking as soon as one is found.
+    pub fn first_longer_than(robots: &[Robot], dimensions: [i32; 2], len: usize) -> Option<i64> {
+        (1..=period(robots, dimensions)).find(|&t| {
+            let positions = positions_at(robots, dimensions, t);
+            Self::longest(&positions) > len
+        })
+    }
+
+    fn longest(positions: &[[i32; 2]]) -> usize {
+        let mut rows = BTreeMap::<_, Vec<_>>::new();
+        for &[x, y] in positions {
+            rows.entry(y).or_default().push(x);
         }
 
-        let seconds = k + 1;
+        rows.into_values()
+            .map(|mut xs| {
+                xs.sort_unstable();
+                xs.dedup();
+
+                let runs = xs.iter().enumerate().chunk_by(|&(i, &x)| x - i as i32);
+                let longest = runs.into_iter().map(|(_, r)| r.count()).max();
+                longest.unwrap_or(0)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Detector for RowRun {
+    fn detect(&self, robots: &[Robot], dimensions: [i32; 2]) -> Option<Detection> {
+        best_of((0..period(robots, dimensions)).map(|t| {
+            let positions = positions_at(robots, dimensions, t);
+            (t, Self::longest(&positions) as f64)
+        }))
+    }
+}
+
+impl fmt::Display for RowRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row run")
+    }
+}
 
-        // Any row/column completely filled?
-        // Not in 1st million
-        //
-        // Any row/column completely empty?
-        // Quite a few in first 1M
-        //
-        // Symmetric around X/Y axis?
-        // Not obviously useful
+/// Robots in a picture are closer together than when scattered.
+struct Variance;
+
+impl Variance {
+    fn of(values: impl IntoIterator<Item = i32>) -> f64 {
+        let values = values.into_iter().map(f64::from).collect::<Vec<_>>();
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n
+    }
+}
 
-        // Any rows of 10+ contiguous robots?
+impl Detector for Variance {
+    fn detect(&self, robots: &[Robot], dimensions: [i32; 2]) -> Option<Detection> {
+        best_of((0..period(robots, dimensions)).map(|t| {
+            let positions = positions_at(robots, dimensions, t);
+            let x = Self::of(positions.iter().map(|p| p[0]));
+            let y = Self::of(positions.iter().map(|p| p[1]));
+            (t, -(x + y))
+        }))
+    }
+}
 
-        let has_row_of_more_than_ten_robots = (0..dimensions[1]).any(|y| {
-            let runs = (0..dimensions[0]).chunk_by(|&x| pos.contains_key(&[x, y]));
-            runs.into_iter().any(|(k, i)| k && i.count() > 10)
-        });
+impl fmt::Display for Variance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "positional variance")
+    }
+}
+
+/// Robots in a picture fill fewer cells of a coarse grid, so the
+/// distribution over cells is less random.
+struct Entropy {
+    /// Cells are at least one tile on each side
+    pub cell: [i32; 2],
+}
 
-        if has_row_of_more_than_ten_robots {
-            print(dimensions, &pos);
-            return seconds;
+impl Entropy {
+    fn of(&self, positions: &[[i32; 2]]) -> f64 {
+        let [w, h] = self.cell.map(|v| v.max(1));
+        let mut cells = BTreeMap::new();
+        for &[x, y] in positions {
+            *cells.entry([x / w, y / h]).or_insert(0) += 1;
         }
+
+        let n = positions.len() as f64;
+        cells
+            .into_values()
+            .map(|count| {
+                let p = f64::from(count) / n;
+                -p * p.log2()
+            })
+            .sum()
+    }
+}
+
+impl Detector for Entropy {
+    fn detect(&self, robots: &[Robot], dimensions: [i32; 2]) -> Option<Detection> {
+        best_of((0..period(robots, dimensions)).map(|t| {
+            let positions = positions_at(robots, dimensions, t);
+            (t, -self.of(&positions))
+        }))
+    }
+}
+
+impl fmt::Display for Entropy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [w, h] = self.cell;
+        write!(f, "entropy over {w}x{h} cells")
+    }
+}
+
+/// X positions repeat every `width` seconds and Y positions every
+/// `height` seconds. Finds when each axis is least spread out, then
+/// combines them with the Chinese remainder theorem. Only looks at
+/// `width + height` times instead of `width * height`.
+struct Crt;
+
+impl Detector for Crt {
+    fn detect(&self, robots: &[Robot], dimensions: [i32; 2]) -> Option<Detection> {
+        let axis = |idx: usize| {
+            best_of((0..i64::from(dimensions[idx])).map(|t| {
+                let positions = positions_at(robots, dimensions, t);
+                (t, -Variance::of(positions.iter().map(|p| p[idx])))
+            }))
+        };
+
+        let x = axis(0)?;
+        let y = axis(1)?;
+
+        let [w, h] = dimensions.map(i64::from);
+        let seconds = crt([x.seconds, w], [y.seconds, h])?;
+        let confidence = f64::min(x.confidence, y.confidence);
+
+        Some(Detection {
+            seconds,
+            confidence,
+        })
+    }
+}
+
+impl fmt::Display for Crt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chinese remainder")
+    }
+}
+
+/// The smallest non-negative `t` with `t = a (mod m)` and `t = b (mod n)`.
+fn crt([a, m]: [i64; 2], [b, n]: [i64; 2]) -> Option<i64> {
+    let (g, p, _) = extended_gcd(m, n);
+
+    if (b - a) % g != 0 {
+        return None;
     }
 
-    panic!("Did not find a result");
+    let lcm = m / g * n;
+    let k = ((b - a) / g * p).rem_euclid(n / g);
+    Some((a + m * k).rem_euclid(lcm))
+}
+
+/// Returns `(g, x, y)` where `a * x + b * y = g`.
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
 }
 
 fn parse(l: &str) -> ([i32; 2], [i32; 2]) {
@@ -88,18 +352,21 @@ fn parse(l: &str) -> ([i32; 2], [i32; 2]) {
     (p, v)
 }
 
-fn simulate(dimensions: [i32; 2], p: [i32; 2], v: [i32; 2], seconds: i32) -> [i32; 2] {
+fn simulate(dimensions: [i32; 2], p: [i32; 2], v: [i32; 2], seconds: i64) -> [i32; 2] {
+    let size = dimensions.map(i64::from);
+
+    // Each axis repeats after `size` seconds, so only the time into
+    // the current lap matters. Keeps the distance from overflowing.
+    let t = size.map(|size| seconds.rem_euclid(size));
+
     // Distance moved in total
-    let d = v.map(|v| v * seconds);
+    let d = [0, 1].map(|i| i64::from(v[i]) * t[i]);
 
     // Next position
-    let n = [p[0] + d[0], p[1] + d[1]];
+    let n = [0, 1].map(|i| i64::from(p[i]) + d[i]);
 
     // Next position, wrapped around the grid edges
-    [
-        n[0].rem_euclid(dimensions[0]),
-        n[1].rem_euclid(dimensions[1]),
-    ]
+    [0, 1].map(|i| n[i].rem_euclid(size[i]) as i32)
 }
 
 fn print(dimensions: [i32; 2], pos: &BTreeMap<[i32; 2], i32>) {
@@ -114,34 +381,133 @@ fn print(dimensions: [i32; 2], pos: &BTreeMap<[i32; 2], i32>) {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Quadrant {
-    Q1,
-    Q2,
-    Q3,
-    Q4,
+/// Rectangles to count robots in. They may overlap or leave gaps.
+#[derive(Debug, Clone, PartialEq)]
+struct Partition {
+    cells: Vec<Rect>,
 }
 
-impl Quadrant {
-    fn categorize(dimensions: [i32; 2], point: [i32; 2]) -> Option<Self> {
-        use std::cmp::Ordering::*;
+#[derive(Debug, Clone, PartialEq)]
+struct Rect {
+    pub x: Range<i32>,
+    pub y: Range<i32>,
+}
 
+impl Rect {
+    fn contains(&self, [x, y]: [i32; 2]) -> bool {
+        self.x.contains(&x) && self.y.contains(&y)
+    }
+}
+
+impl Partition {
+    pub fn new(cells: impl IntoIterator<Item = Rect>) -> Self {
+        Self {
+            cells: cells.into_iter().collect(),
+        }
+    }
+
+    /// The four quadrants; the middle row and column belong to no
+    /// cell.
+    pub fn quadrants(dimensions: [i32; 2]) -> Self {
+        let [w, h] = dimensions;
         let [mx, my] = dimensions.map(|v| v / 2);
-        let [x, y] = point;
 
-        let q = match (x.cmp(&mx), y.cmp(&my)) {
-            (_, Equal) | (Equal, _) => return None,
+        let left = 0..mx;
+        let right = mx + 1..w;
+        let top = 0..my;
+        let bottom = my + 1..h;
+
+        Self::new(
+            [
+                (left.clone(), top.clone()),
+                (right.clone(), top),
+                (left, bottom.clone()),
+                (right, bottom),
+            ]
+            .map(|(x, y)| Rect { x, y }),
+        )
+    }
 
-            (Less, Less) => Self::Q1,
-            (Greater, Less) => Self::Q2,
-            (Less, Greater) => Self::Q3,
-            (Greater, Greater) => Self::Q4,
-        };
+    /// `columns` by `rows` cells covering the whole area, in reading
+    /// order. Cells differ in size by at most one when the dimensions
+    /// don't divide evenly.
+    pub fn grid(dimensions: [i32; 2], columns: i32, rows: i32) -> Self {
+        let [w, h] = dimensions;
+        let edge = |i, n, size| i * size / n;
+
+        let cells = (0..rows).flat_map(|r| {
+            (0..columns).map(move |c| Rect {
+                x: edge(c, columns, w)..edge(c + 1, columns, w),
+                y: edge(r, rows, h)..edge(r + 1, rows, h),
+            })
+        });
+
+        Self::new(cells)
+    }
+
+    pub fn counts(&self, positions: &[[i32; 2]]) -> Vec<usize> {
+        self.cells
+            .iter()
+            .map(|cell| positions.iter().filter(|&&p| cell.contains(p)).count())
+            .collect()
+    }
 
-        Some(q)
+    /// The product of every non-empty cell's count.
+    pub fn safety_factor(&self, positions: &[[i32; 2]]) -> usize {
+        let counts = self.counts(positions).into_iter();
+        counts.filter(|&c| c != 0).product()
     }
 }
 
+/// Robots per cell at each time in `times`.
+fn partition_counts(
+    robots: &[Robot],
+    dimensions: [i32; 2],
+    partition: &Partition,
+    times: Range<i64>,
+) -> Vec<(i64, Vec<usize>)> {
+    times
+        .map(|t| {
+            let positions = positions_at(robots, dimensions, t);
+            (t, partition.counts(&positions))
+        })
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct SafetyStats {
+    pub min: usize,
+    /// The first time the minimum occurs
+    pub min_at: i64,
+    pub max: usize,
+    pub mean: f64,
+}
+
+fn safety_stats(
+    robots: &[Robot],
+    dimensions: [i32; 2],
+    partition: &Partition,
+    times: Range<i64>,
+) -> Option<SafetyStats> {
+    let factors = times
+        .map(|t| {
+            let positions = positions_at(robots, dimensions, t);
+            (t, partition.safety_factor(&positions))
+        })
+        .collect::<Vec<_>>();
+
+    let &(min_at, min) = factors.iter().min_by_key(|&&(_, f)| f)?;
+    let &(_, max) = factors.iter().max_by_key(|&&(_, f)| f)?;
+    let mean = factors.iter().map(|&(_, f)| f as f64).sum::<f64>() / factors.len() as f64;
+
+    Some(SafetyStats {
+        min,
+        min_at,
+        max,
+        mean,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -152,5 +518,149 @@ mod test {
     #[test]
     fn example() {
         assert_eq!(12, safety_factor(EXAMPLE, DIMENSIONS, 100));
+
+        // Empty quadrants are left out of the product
+        assert_eq!(1, safety_factor("p=0,0 v=1,1", DIMENSIONS, 0));
+    }
+
+    /// Robots that fill an 11x5 block at `seconds` and scatter
+    /// otherwise.
+    fn hidden_block(dimensions: [i32; 2], seconds: i64) -> Vec<Robot> {
+        let mut seed = 12345_u32;
+        let mut random = move |range: i32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            ((seed >> 16) % range as u32) as i32
+        };
+
+        let mut robots = Vec::new();
+        for y in 10..15 {
+            for x in 10..21 {
+                let v = [random(21) - 10, random(21) - 10];
+                let p = simulate(dimensions, [x, y], v, -seconds);
+                robots.push((p, v));
+            }
+        }
+        robots
+    }
+
+    #[test]
+    fn detectors() {
+        let dimensions = [31, 37];
+        let robots = hidden_block(dimensions, 500);
+
+        for (detector, detection) in detect_all(&robots, dimensions, DETECTORS) {
+            let detection = detection.unwrap();
+            assert_eq!(500, detection.seconds, "{detector}");
+            assert!(detection.confidence > 3.0, "{detector}: {detection:?}");
+        }
+
+        // Zero-sized cells are widened to one tile
+        let thin = Entropy { cell: [0, 1] }.detect(&robots, dimensions);
+        let unit = Entropy { cell: [1, 1] }.detect(&robots, dimensions);
+        assert_eq!(unit, thin);
+    }
+
+    #[test]
+    fn chinese_remainder() {
+        assert_eq!(Some(500), crt([500 % 31, 31], [500 % 37, 37]));
+        assert_eq!(Some(7502), crt([7502 % 101, 101], [7502 % 103, 103]));
+        assert_eq!(Some(10), crt([4, 6], [2, 4]));
+        assert_eq!(None, crt([1, 6], [2, 4]));
+    }
+
+    #[test]
+    fn time_jumping() {
+        let mut robots = EXAMPLE.lines().map(parse).collect::<Vec<_>>();
+        let jumped = configuration_at(&robots, DIMENSIONS, 100);
+
+        for (p, v) in &mut robots {
+            for _ in 0..100 {
+                *p = simulate(DIMENSIONS, *p, *v, 1);
+            }
+        }
+        assert_eq!(jumped, configuration_at(&robots, DIMENSIONS, 0));
+
+        // Far in the future is the same as the matching time within
+        // one period
+        let robots = [([3, 4], [100, -100])];
+        let far = 30_000_000_000;
+        let near = far % period(&robots, DIMENSIONS);
+        assert_eq!(
+            configuration_at(&robots, DIMENSIONS, near),
+            configuration_at(&robots, DIMENSIONS, far),
+        );
+    }
+
+    #[test]
+    fn periods() {
+        let robots = EXAMPLE.lines().map(parse).collect::<Vec<_>>();
+        assert_eq!(77, period(&robots, DIMENSIONS));
+        assert_eq!(
+            configuration_at(&robots, DIMENSIONS, 5),
+            configuration_at(&robots, DIMENSIONS, 5 + 77)
+        );
+
+        // Standing still
+        assert_eq!(1, period(&[([1, 1], [0, 0])], DIMENSIONS));
+        assert_eq!(1, period(&[([1, 1], [11, -7])], DIMENSIONS));
+
+        // Only moves horizontally, half way around
+        let robots = [([0, 0], [2, 0]), ([2, 0], [2, 0])];
+        assert_eq!(2, period(&robots, [4, 1]));
+
+        // ...so the robots swap and the second state is the same
+        let states = distinct_states(&robots, [4, 1]).collect::<Vec<_>>();
+        assert_eq!(1, states.len());
+        assert_eq!(0, states[0].0);
+
+        // Large coprime sides give a period beyond `i32`
+        let robots = [([0, 0], [1, 1])];
+        assert_eq!(65521 * 65519, period(&robots, [65521, 65519]));
+
+        // ...and every time in it can be reached
+        let last = period(&robots, [65521, 65519]) - 1;
+        let mut expected = BTreeMap::new();
+        expected.insert([65520, 65518], 1);
+        assert_eq!(expected, configuration_at(&robots, [65521, 65519], last));
+    }
+
+    #[test]
+    fn partitions() {
+        let robots = EXAMPLE.lines().map(parse).collect::<Vec<_>>();
+        let quadrants = Partition::quadrants(DIMENSIONS);
+        let counts = partition_counts(&robots, DIMENSIONS, &quadrants, 100..101);
+        assert_eq!(vec![(100, vec![1, 3, 4, 1])], counts);
+
+        let grid = Partition::grid(DIMENSIONS, 2, 3);
+        assert_eq!(6, grid.cells.len());
+        assert_eq!(Rect { x: 5..11, y: 4..7 }, grid.cells[5]);
+
+        // The grid covers every tile exactly once
+        let counts = partition_counts(&robots, DIMENSIONS, &grid, 0..20);
+        let n_robots = robots.len();
+        assert!(
+            counts
+                .iter()
+                .all(|(_, c)| c.iter().sum::<usize>() == n_robots)
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let robots = EXAMPLE.lines().map(parse).collect::<Vec<_>>();
+        let quadrants = Partition::quadrants(DIMENSIONS);
+        let stats = safety_stats(&robots, DIMENSIONS, &quadrants, 100..101).unwrap();
+        assert_eq!(12, stats.min);
+        assert_eq!(12, stats.max);
+        assert_eq!(100, stats.min_at);
+
+        let stats = safety_stats(&robots, DIMENSIONS, &quadrants, 0..77).unwrap();
+        assert!(stats.min <= 12 && 12 <= stats.max);
+        assert!(stats.min as f64 <= stats.mean && stats.mean <= stats.max as f64);
+
+        let positions = positions_at(&robots, DIMENSIONS, stats.min_at);
+        assert_eq!(stats.min, quadrants.safety_factor(&positions));
+
+        assert_eq!(None, safety_stats(&robots, DIMENSIONS, &quadrants, 0..0));
     }
 }
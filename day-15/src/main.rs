@@ -1,3 +1,8 @@
+// Only some of these are reachable from `main`; the tests cover the rest
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+
 const INPUT: &str = include_str!("../input.txt");
 
 fn main() {
@@ -6,153 +11,95 @@ fn main() {
 }
 
 mod part1 {
-    use std::{collections::BTreeMap, iter};
-
-    use super::{Coord, Direction};
+    use super::Warehouse;
 
     pub fn gps_sum(s: &str) -> usize {
-        let (m, i) = s.split_once("\n\n").expect("input malformed");
-
-        let mut robot = None;
-        let mut map = BTreeMap::new();
-        for (y, l) in m.lines().enumerate() {
-            for (x, c) in l.chars().enumerate() {
-                if c == '@' {
-                    assert!(robot.is_none());
-                    robot = Some([x, y]);
-                } else if c != '.' {
-                    let p = Piece::try_from(c).unwrap();
-                    map.insert([x, y], p);
-                }
-            }
-        }
-        let mut robot = robot.expect("No robot found");
-
-        let instructions = i
-            .lines()
-            .flat_map(|l| l.chars())
-            .map(|c| Direction::try_from(c).unwrap());
-
-        // print(&map, robot);
-
-        for i in instructions {
-            use Piece::*;
-
-            let pos = iter::successors(Some(robot), |&r| i.move_it(r)).skip(1);
-
-            let mut to_push = 0;
-            for c in pos {
-                match map.get(&c) {
-                    None => {
-                        // eprintln!("Found a free spot, moving {to_push} boxes");
-                        robot = i.move_it(robot).unwrap();
-
-                        let old = map.remove(&robot);
-                        assert_ne!(old, Some(Wall));
+        let (mut warehouse, instructions) = Warehouse::parse(s, 1);
+        warehouse.run(instructions);
+        warehouse.gps_sum()
+    }
 
-                        if to_push > 0 {
-                            let old = map.insert(c, Box);
-                            assert!(old.is_none());
-                        }
+    #[cfg(test)]
+    mod test {
+        use super::*;
 
-                        break;
-                    }
-                    Some(Box) => {
-                        to_push += 1;
-                    }
-                    Some(Wall) => {
-                        // eprintln!("Found a wall, doing nothing");
-                        break;
-                    }
-                }
-            }
+        const EXAMPLE_1: &str = include_str!("../example-1.txt");
+        const EXAMPLE_2: &str = include_str!("../example-2.txt");
 
-            // println!("\n{i:?}\n");
-            // print(&map, robot);
+        #[test]
+        fn example_1() {
+            assert_eq!(10092, gps_sum(EXAMPLE_1));
         }
 
-        map.iter()
-            .filter(|&(_, &p)| p == Piece::Box)
-            .map(|(&[x, y], _)| y * 100 + x)
-            .sum()
-    }
-
-    #[allow(dead_code)]
-    fn print(map: &BTreeMap<Coord, Piece>, robot: Coord) {
-        let (&[max_x, max_y], _) = map.last_key_value().unwrap();
-
-        for y in 0..=max_y {
-            for x in 0..=max_x {
-                if robot == [x, y] {
-                    print!("@");
-                } else {
-                    match map.get(&[x, y]) {
-                        None => print!("."),
-                        Some(Piece::Wall) => print!("#"),
-                        Some(Piece::Box) => print!("O"),
-                    }
-                }
-            }
-            println!();
+        #[test]
+        fn example_2() {
+            assert_eq!(2028, gps_sum(EXAMPLE_2));
         }
     }
+}
 
-    #[derive(Debug, Copy, Clone, PartialEq)]
-    enum Piece {
-        Wall,
-        Box,
-    }
-
-    impl TryFrom<char> for Piece {
-        type Error = &'static str;
-
-        fn try_from(value: char) -> Result<Self, Self::Error> {
-            use Piece::*;
+mod part2 {
+    use super::Warehouse;
 
-            let v = match value {
-                '#' => Wall,
-                'O' => Box,
-                _ => return Err("Unknown piece"),
-            };
-            Ok(v)
-        }
+    pub fn gps_sum(s: &str) -> usize {
+        let (mut warehouse, instructions) = Warehouse::parse(s, 2);
+        warehouse.run(instructions);
+        warehouse.gps_sum()
     }
 
     #[cfg(test)]
     mod test {
         use super::*;
+        use crate::Simulator;
 
         const EXAMPLE_1: &str = include_str!("../example-1.txt");
         const EXAMPLE_2: &str = include_str!("../example-2.txt");
+        const EXAMPLE_3: &str = include_str!("../example-3.txt");
 
         #[test]
         fn example_1() {
-            assert_eq!(10092, gps_sum(EXAMPLE_1));
+            assert_eq!(9021, gps_sum(EXAMPLE_1));
         }
 
         #[test]
         fn example_2() {
-            assert_eq!(2028, gps_sum(EXAMPLE_2));
+            assert_eq!(1751, gps_sum(EXAMPLE_2));
+            assert_eq!(Ok(()), Simulator::new(EXAMPLE_2, 2).seek(usize::MAX));
+        }
+
+        #[test]
+        fn example_3() {
+            assert_eq!(618, gps_sum(EXAMPLE_3));
+            assert_eq!(Ok(()), Simulator::new(EXAMPLE_3, 2).seek(usize::MAX));
         }
     }
 }
 
-mod part2 {
-    use std::collections::{BTreeMap, BTreeSet};
+/// Everything in the map is stretched horizontally by `scale`, so
+/// each box is `scale` tiles wide.
+#[derive(Debug, Clone)]
+struct Warehouse {
+    map: Map,
+    robot: Coord,
+    scale: usize,
+}
 
-    use super::{Coord, Direction};
+type Map = BTreeMap<Coord, Piece>;
 
-    pub fn gps_sum(s: &str) -> usize {
-        use Piece::*;
+impl Warehouse {
+    fn parse(s: &str, scale: usize) -> (Self, Vec<Direction>) {
+        assert!(scale > 0, "Scale must be at least 1");
 
-        let (m, i) = s.split_once("\n\n").expect("malformed input");
+        let [m, i] = &blank_line_groups(s)[..] else {
+            panic!("malformed input");
+        };
 
         let mut map = BTreeMap::new();
         let mut robot = None;
 
-        for (y, l) in m.lines().enumerate() {
+        for (y, l) in m.iter().enumerate() {
             for (x, c) in l.chars().enumerate() {
-                let x = x * 2;
+                let x = x * scale;
 
                 match c {
                     '.' => {}
@@ -163,13 +110,15 @@ mod part2 {
                     }
 
                     '#' => {
-                        map.insert([x, y], Wall);
-                        map.insert([x + 1, y], Wall);
+                        for dx in 0..scale {
+                            map.insert([x + dx, y], Piece::Wall);
+                        }
                     }
 
                     'O' => {
-                        map.insert([x, y], BoxLeft);
-                        map.insert([x + 1, y], BoxRight);
+                        for part in 0..scale {
+                            map.insert([x + part, y], Piece::Box(part));
+                        }
                     }
 
                     _ => panic!("Unknown character {c}"),
@@ -177,111 +126,123 @@ mod part2 {
             }
         }
 
-        let mut robot = robot.expect("No robot found");
-
-        // print(&map, robot);
+        let robot = robot.expect("No robot found");
 
         let instructions = i
-            .lines()
-            .flat_map(|l| l.chars())
-            .map(|c| Direction::try_from(c).unwrap());
+            .iter()
+            .flat_map(|l| l.trim_end().chars())
+            .map(|c| Direction::try_from(c).unwrap())
+            .collect();
 
-        let mut to_move = BTreeSet::new();
-        let mut pieces_to_move = Vec::new();
+        (Self { map, robot, scale }, instructions)
+    }
 
+    fn run(&mut self, instructions: impl IntoIterator<Item = Direction>) {
         for i in instructions {
-            to_move.clear();
-            pieces_to_move.clear();
-
-            let target = i.move_it(robot).unwrap();
-
-            if can_move(&map, &mut to_move, target, i) {
-                // Remove the pieces that are moving
-                let old_pieces = to_move
-                    .iter()
-                    .map(|&c| map.remove(&c).expect("Moving something that isn't there"));
-
-                pieces_to_move.extend(old_pieces);
+            self.step(i);
+        }
+    }
 
-                // Re-insert at their new position
-                for (&c, &p) in to_move.iter().zip(&pieces_to_move) {
-                    let nc = i.move_it(c).unwrap();
-                    map.insert(nc, p);
-                }
+    /// Moves the robot, pushing any boxes in the way. Returns the
+    /// tiles whose pieces moved, or `None` if something is blocked.
+    fn step(&mut self, i: Direction) -> Option<BTreeSet<Coord>> {
+        let target = i.move_it(self.robot).unwrap();
 
-                // Move the robot
-                robot = target;
-            }
+        let mut to_move = BTreeSet::new();
+        if !self.can_move(&mut to_move, target, i) {
+            return None;
+        }
 
-            // println!("\n=== {i:?}");
-            // print(&map, robot);
+        // Remove the pieces that are moving
+        let pieces = to_move
+            .iter()
+            .map(|c| {
+                self.map
+                    .remove(c)
+                    .expect("Moving something that isn't there")
+            })
+            .collect::<Vec<_>>();
+
+        // Re-insert at their new position
+        for (&c, p) in to_move.iter().zip(pieces) {
+            let nc = i.move_it(c).unwrap();
+            self.map.insert(nc, p);
         }
 
-        map.iter()
-            .filter(|&(_, &p)| p == BoxLeft)
-            .map(|(&[x, y], _)| y * 100 + x)
-            .sum()
+        // Move the robot
+        self.robot = target;
+
+        Some(to_move)
     }
 
-    fn can_move(map: &Map, to_move: &mut BTreeSet<Coord>, target: Coord, i: Direction) -> bool {
+    fn can_move(&self, to_move: &mut BTreeSet<Coord>, target: Coord, i: Direction) -> bool {
         use Direction::*;
-        use Piece::*;
 
-        let mut split_move = |l_target, r_target| {
-            to_move.insert(l_target);
-            to_move.insert(r_target);
-
-            let l_target = i.move_it(l_target).unwrap();
-            let r_target = i.move_it(r_target).unwrap();
-
-            can_move(map, to_move, l_target, i) && can_move(map, to_move, r_target, i)
+        let part = match self.map.get(&target) {
+            None => return true,
+            Some(Piece::Wall) => return false,
+            Some(&Piece::Box(part)) => part,
         };
 
-        let v = match (map.get(&target), i) {
-            (Some(Wall), _) => false,
-
-            (Some(BoxLeft), R) | (Some(BoxRight), L) => {
-                to_move.insert(target);
-                let target = i.move_it(target).unwrap();
-                to_move.insert(target);
-                let target = i.move_it(target).unwrap();
-                can_move(map, to_move, target, i)
-            }
-
-            (Some(BoxLeft), U | D) => split_move(target, R.move_it(target).unwrap()),
-
-            (Some(BoxRight), U | D) => split_move(L.move_it(target).unwrap(), target),
-
-            (None, _) => true,
-
-            o => unreachable!("{o:?} is impossible"),
-        };
+        // Already being pushed by another route
+        if to_move.contains(&target) {
+            return true;
+        }
 
-        // eprintln!("{target:?} {i:?}: {v}");
+        let [x, y] = target;
+        let left = x - part;
+        let cells = (left..left + self.scale).map(|x| [x, y]);
+        to_move.extend(cells.clone());
+
+        match i {
+            // Only the leading edge of the box can hit anything
+            L => self.can_move(to_move, [left - 1, y], i),
+            R => self.can_move(to_move, [left + self.scale, y], i),
+
+            U | D => cells
+                .map(|c| i.move_it(c).unwrap())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .all(|c| self.can_move(to_move, c, i)),
+        }
+    }
 
-        v
+    fn walls(&self) -> BTreeSet<Coord> {
+        self.map
+            .iter()
+            .filter(|&(_, &p)| p == Piece::Wall)
+            .map(|(&c, _)| c)
+            .collect()
     }
 
-    type Map = BTreeMap<Coord, Piece>;
+    fn gps_sum(&self) -> usize {
+        self.map
+            .iter()
+            .filter(|&(_, &p)| p == Piece::Box(0))
+            .map(|(&[x, y], _)| y * 100 + x)
+            .sum()
+    }
 
     #[allow(dead_code)]
-    fn print(map: &Map, robot: Coord) {
-        use Piece::*;
-
-        let (&[max_x, max_y], _) = map.last_key_value().expect("map empty");
+    fn print(&self) {
+        let (&[max_x, max_y], _) = self.map.last_key_value().expect("map empty");
 
         for y in 0..=max_y {
             for x in 0..=max_x {
                 let c = [x, y];
 
-                let s = if robot == c {
-                    "@"
+                let s = if self.robot == c {
+                    '@'
                 } else {
-                    match map.get(&c) {
-                        Some(Wall) => "#",
-                        Some(BoxLeft) => "[",
-                        Some(BoxRight) => "]",
-                        None => ".",
+                    match self.map.get(&c) {
+                        Some(Piece::Wall) => '#',
+                        Some(&Piece::Box(part)) => match (part, self.scale) {
+                            (_, 1) => 'O',
+                            (0, _) => '[',
+                            (p, s) if p == s - 1 => ']',
+                            _ => '=',
+                        },
+                        None => '.',
                     }
                 };
 
@@ -290,37 +251,217 @@ mod part2 {
             println!();
         }
     }
+}
+
+/// Replays instructions one at a time, checking that the warehouse
+/// still makes sense after every move.
+#[derive(Debug)]
+struct Simulator {
+    warehouse: Warehouse,
+    instructions: Vec<Direction>,
+    history: Vec<Move>,
+    walls: BTreeSet<Coord>,
+    /// The map as of the last check, kept up to date only for the
+    /// tiles each move touches
+    snapshot: Map,
+}
+
+#[derive(Debug)]
+struct Move {
+    direction: Direction,
+    robot: Coord,
+    /// Where the moved pieces were before the move
+    moved: BTreeSet<Coord>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Violation {
+    /// Index of the instruction that broke things
+    pub instruction: usize,
+    pub problem: Problem,
+}
 
-    #[derive(Debug, Copy, Clone, PartialEq)]
-    enum Piece {
-        Wall,
-        BoxLeft,
-        BoxRight,
+#[derive(Debug, Clone, PartialEq)]
+enum Problem {
+    RobotOnPiece(Coord),
+    /// A box tile isn't next to the rest of its box
+    BrokenBox(Coord),
+    WallsChanged,
+}
+
+impl Simulator {
+    pub fn new(s: &str, scale: usize) -> Self {
+        let (warehouse, instructions) = Warehouse::parse(s, scale);
+        let walls = warehouse.walls();
+        let snapshot = warehouse.map.clone();
+
+        Self {
+            warehouse,
+            instructions,
+            history: Vec::new(),
+            walls,
+            snapshot,
+        }
     }
 
-    #[cfg(test)]
-    mod test {
-        use super::*;
+    /// How many instructions have been applied.
+    pub fn position(&self) -> usize {
+        self.history.len()
+    }
 
-        const EXAMPLE_1: &str = include_str!("../example-1.txt");
-        const EXAMPLE_2: &str = include_str!("../example-2.txt");
-        const EXAMPLE_3: &str = include_str!("../example-3.txt");
+    /// Applies the next instruction. Returns `false` once they have
+    /// all been applied.
+    pub fn step(&mut self) -> Result<bool, Violation> {
+        let instruction = self.position();
+        let Some(&direction) = self.instructions.get(instruction) else {
+            return Ok(false);
+        };
 
-        #[test]
-        fn example_1() {
-            assert_eq!(9021, gps_sum(EXAMPLE_1));
+        let robot = self.warehouse.robot;
+        let moved = self.warehouse.step(direction).unwrap_or_default();
+        let touched = touched_tiles(direction, &moved);
+        self.history.push(Move {
+            direction,
+            robot,
+            moved,
+        });
+
+        let checked = self.check(&touched);
+        self.sync(&touched);
+
+        checked
+            .map_err(|problem| Violation {
+                instruction,
+                problem,
+            })
+            .map(|_| true)
+    }
+
+    /// Reverts the last instruction. Returns `false` if there was
+    /// nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(Move {
+            direction,
+            robot,
+            moved,
+        }) = self.history.pop()
+        else {
+            return false;
+        };
+
+        let map = &mut self.warehouse.map;
+        let pieces = moved
+            .iter()
+            .map(|&c| {
+                let nc = direction.move_it(c).unwrap();
+                map.remove(&nc).expect("Undoing something that isn't there")
+            })
+            .collect::<Vec<_>>();
+
+        let touched = touched_tiles(direction, &moved);
+
+        map.extend(moved.into_iter().zip(pieces));
+        self.warehouse.robot = robot;
+        self.sync(&touched);
+
+        true
+    }
+
+    /// Steps forward or undoes until `n` instructions have been applied.
+    pub fn seek(&mut self, n: usize) -> Result<(), Violation> {
+        let n = n.min(self.instructions.len());
+
+        while self.position() > n {
+            self.undo();
+        }
+        while self.position() < n {
+            self.step()?;
         }
 
-        #[test]
-        fn example_2() {
-            gps_sum(EXAMPLE_2);
+        Ok(())
+    }
+
+    /// Only looks at the tiles the last move touched, and their
+    /// neighbors in case part of a box was left behind.
+    fn check(&self, touched: &BTreeSet<Coord>) -> Result<(), Problem> {
+        let Warehouse { map, robot, scale } = &self.warehouse;
+
+        if map.contains_key(robot) {
+            return Err(Problem::RobotOnPiece(*robot));
         }
 
-        #[test]
-        fn example_3() {
-            gps_sum(EXAMPLE_3);
+        // Walls never move and nothing can move onto them
+        let is_wall = |c| {
+            self.walls.contains(c)
+                || self.snapshot.get(c) == Some(&Piece::Wall)
+                || map.get(c) == Some(&Piece::Wall)
+        };
+        if touched.iter().any(is_wall) {
+            return Err(Problem::WallsChanged);
+        }
+
+        let nearby = touched
+            .iter()
+            .flat_map(|&[x, y]| {
+                [x.checked_sub(1), Some(x), x.checked_add(1)].map(|x| Some([x?, y]))
+            })
+            .flatten()
+            .collect::<BTreeSet<Coord>>();
+
+        for [x, y] in nearby {
+            let Some(&Piece::Box(part)) = map.get(&[x, y]) else {
+                continue;
+            };
+
+            // Every tile must have the next one to its right
+            let last = part + 1 == *scale;
+            let intact = last || map.get(&[x + 1, y]) == Some(&Piece::Box(part + 1));
+
+            // ...and the first must start it off
+            let started = part == 0
+                || x.checked_sub(1).and_then(|x| map.get(&[x, y])) == Some(&Piece::Box(part - 1));
+
+            if !intact || !started || part >= *scale {
+                return Err(Problem::BrokenBox([x, y]));
+            }
         }
+
+        Ok(())
     }
+
+    /// Brings the snapshot up to date for these tiles.
+    fn sync(&mut self, tiles: &BTreeSet<Coord>) {
+        for c in tiles {
+            match self.warehouse.map.get(c) {
+                Some(&p) => self.snapshot.insert(*c, p),
+                None => self.snapshot.remove(c),
+            };
+        }
+    }
+}
+
+/// Where the moved pieces were, and where they were pushed to.
+fn touched_tiles(direction: Direction, moved: &BTreeSet<Coord>) -> BTreeSet<Coord> {
+    let destinations = moved.iter().map(|&c| direction.move_it(c).unwrap());
+    moved.iter().copied().chain(destinations).collect()
+}
+
+/// The lines between blank lines, with either LF or CRLF endings.
+fn blank_line_groups(s: &str) -> Vec<Vec<&str>> {
+    let lines = s.lines().collect::<Vec<_>>();
+
+    lines
+        .split(|l| l.trim_end().is_empty())
+        .filter(|g| !g.is_empty())
+        .map(<[_]>::to_vec)
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Piece {
+    Wall,
+    /// Which tile of the box this is, counting from the left
+    Box(usize),
 }
 
 type Coord = [usize; 2];
@@ -368,3 +509,159 @@ impl Direction {
         Some(c)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE_1: &str = include_str!("../example-1.txt");
+    const EXAMPLE_3: &str = include_str!("../example-3.txt");
+
+    fn gps_sum(s: &str, scale: usize) -> usize {
+        let (mut warehouse, instructions) = Warehouse::parse(s, scale);
+        warehouse.run(instructions);
+        warehouse.gps_sum()
+    }
+
+    #[test]
+    fn any_scale() {
+        assert_eq!(908, gps_sum(EXAMPLE_3, 1));
+        assert_eq!(618, gps_sum(EXAMPLE_3, 2));
+
+        let crlf = format!("{EXAMPLE_3}\n").replace('\n', "\r\n");
+        assert_eq!(618, gps_sum(&crlf, 2));
+
+        for scale in 1..=4 {
+            let (mut warehouse, instructions) = Warehouse::parse(EXAMPLE_1, scale);
+            let n_boxes = warehouse
+                .map
+                .values()
+                .filter(|&&p| p == Piece::Box(0))
+                .count();
+            warehouse.run(instructions);
+            let n_boxes_after = warehouse
+                .map
+                .values()
+                .filter(|&&p| p == Piece::Box(0))
+                .count();
+            assert_eq!(n_boxes, n_boxes_after);
+        }
+    }
+
+    #[test]
+    fn wide_boxes_push_together() {
+        // One box under two others, which are under a wall
+        let mut map = BTreeMap::new();
+        for x in 0..16 {
+            map.insert([x, 0], Piece::Wall);
+        }
+        for (left, y) in [(5, 2), (9, 2), (7, 3)] {
+            for part in 0..3 {
+                map.insert([left + part, y], Piece::Box(part));
+            }
+        }
+        let mut warehouse = Warehouse {
+            map,
+            robot: [8, 4],
+            scale: 3,
+        };
+
+        let moved = warehouse.step(Direction::U).unwrap();
+        assert_eq!(9, moved.len());
+        assert_eq!([8, 3], warehouse.robot);
+        assert_eq!(Some(&Piece::Box(0)), warehouse.map.get(&[5, 1]));
+        assert_eq!(Some(&Piece::Box(0)), warehouse.map.get(&[9, 1]));
+
+        // Now the top boxes are against the wall
+        assert_eq!(None, warehouse.step(Direction::U));
+    }
+
+    #[test]
+    fn step_undo_seek() {
+        let mut sim = Simulator::new(EXAMPLE_1, 2);
+        let start = sim.warehouse.clone();
+        let n = sim.instructions.len();
+
+        sim.seek(n).unwrap();
+        assert_eq!(9021, sim.warehouse.gps_sum());
+        assert_eq!(Ok(false), sim.step());
+
+        sim.seek(100).unwrap();
+        let at_100 = sim.warehouse.clone();
+
+        sim.seek(0).unwrap();
+        assert_eq!(start.map, sim.warehouse.map);
+        assert_eq!(start.robot, sim.warehouse.robot);
+        assert!(!sim.undo());
+
+        for _ in 0..100 {
+            assert_eq!(Ok(true), sim.step());
+        }
+        assert_eq!(at_100.map, sim.warehouse.map);
+        assert_eq!(at_100.robot, sim.warehouse.robot);
+    }
+
+    #[test]
+    fn violations() {
+        const PUSH: &str = "######\n#@O..#\n######\n\n>>";
+
+        // Turn the right half of the box into another left half
+        let mut sim = Simulator::new(PUSH, 2);
+        sim.seek(1).unwrap();
+        sim.warehouse.map.insert([5, 1], Piece::Box(0));
+        assert_eq!(
+            Err(Violation {
+                instruction: 1,
+                problem: Problem::BrokenBox([5, 1]),
+            }),
+            sim.step(),
+        );
+
+        // Claim there is a wall where the box is about to go
+        let mut sim = Simulator::new(PUSH, 2);
+        sim.walls.insert([6, 1]);
+        assert_eq!(
+            Err(Violation {
+                instruction: 1,
+                problem: Problem::WallsChanged,
+            }),
+            sim.seek(2),
+        );
+        assert_eq!(2, sim.position());
+
+        // The snapshot still remembers a wall where the box is about
+        // to go
+        let mut sim = Simulator::new(PUSH, 2);
+        sim.seek(1).unwrap();
+        sim.snapshot.insert([6, 1], Piece::Wall);
+        assert_eq!(
+            Err(Violation {
+                instruction: 1,
+                problem: Problem::WallsChanged,
+            }),
+            sim.step(),
+        );
+
+        // Tiles the move doesn't touch aren't looked at
+        let mut sim = Simulator::new(PUSH, 2);
+        sim.seek(1).unwrap();
+        sim.warehouse.map.remove(&[0, 0]);
+        assert_eq!(Ok(true), sim.step());
+
+        // The robot stays put after bumping into the wall, so it is
+        // still standing on whatever was dropped on it
+        let mut sim = Simulator::new(EXAMPLE_3, 2);
+        sim.instructions = vec![Direction::R, Direction::R];
+        sim.seek(1).unwrap();
+        let robot = sim.warehouse.robot;
+        sim.warehouse.map.insert(robot, Piece::Wall);
+        sim.walls.insert(robot);
+        assert_eq!(
+            Err(Violation {
+                instruction: 1,
+                problem: Problem::RobotOnPiece(robot),
+            }),
+            sim.step(),
+        );
+    }
+}